@@ -0,0 +1,108 @@
+// Checking of the answers typed during a review. The typed text and the
+// expected one are normalized, then compared character by character.
+
+use crate::scheduler::Grade;
+
+// LaTeX commands that only add space, they are removed before comparing
+const SPACING_COMMANDS: [&str; 7] = ["\\,", "\\;", "\\:", "\\!", "\\quad", "\\qquad", "\\ "];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffPart {
+    Correct(char),
+    Missing(char), // Expected but not typed
+    Extra(char),   // Typed but not expected
+}
+
+//Spacing commands and "~" become spaces, runs of whitespace become one space,
+//the ends are trimmed
+pub fn normalize(text: &str) -> String {
+    let mut text = text.replace('~', " ");
+    // The longest commands first, "\qquad" contains "\quad"
+    let mut commands = SPACING_COMMANDS;
+    commands.sort_by_key(|command| std::cmp::Reverse(command.len()));
+    for command in commands {
+        text = text.replace(command, " ");
+    }
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+//Shortest edit between the normalized texts, from their longest common
+//subsequence of characters
+pub fn diff(typed: &str, expected: &str) -> Vec<DiffPart> {
+    let typed: Vec<char> = normalize(typed).chars().collect();
+    let expected: Vec<char> = normalize(expected).chars().collect();
+    // common[i][j] is the longest common subsequence of typed[i..] and expected[j..]
+    let mut common = vec![vec![0usize; expected.len() + 1]; typed.len() + 1];
+    for i in (0..typed.len()).rev() {
+        for j in (0..expected.len()).rev() {
+            common[i][j] = if typed[i] == expected[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+    let mut parts = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < typed.len() || j < expected.len() {
+        if i < typed.len() && j < expected.len() && typed[i] == expected[j] {
+            parts.push(DiffPart::Correct(typed[i]));
+            i += 1;
+            j += 1;
+        } else if j < expected.len() && (i == typed.len() || common[i][j + 1] >= common[i + 1][j]) {
+            parts.push(DiffPart::Missing(expected[j]));
+            j += 1;
+        } else {
+            parts.push(DiffPart::Extra(typed[i]));
+            i += 1;
+        }
+    }
+    parts
+}
+
+//Good for an exact answer, Hard for a small typo, Again otherwise
+pub fn suggest_grade(parts: &[DiffPart]) -> Grade {
+    let correct = parts
+        .iter()
+        .filter(|part| matches!(part, DiffPart::Correct(_)))
+        .count();
+    if correct == parts.len() {
+        return Grade::Good;
+    }
+    // At most one error every ten characters
+    if (parts.len() - correct) * 10 <= parts.len() {
+        return Grade::Hard;
+    }
+    Grade::Again
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffPart::*;
+
+    #[test]
+    fn normalized_comparison() {
+        assert_eq!(normalize("  a\\,b \\qquad  c~d\n"), "a b c d");
+        let parts = diff("x\\, +  y", "x + y");
+        assert!(parts.iter().all(|part| matches!(part, Correct(_))));
+        assert_eq!(suggest_grade(&parts), Grade::Good);
+    }
+
+    #[test]
+    fn character_diff() {
+        assert_eq!(
+            diff("cat", "cart"),
+            vec![Correct('c'), Correct('a'), Missing('r'), Correct('t')]
+        );
+        assert_eq!(
+            diff("abx", "ab"),
+            vec![Correct('a'), Correct('b'), Extra('x')]
+        );
+        assert_eq!(suggest_grade(&diff("definiton", "definition")), Grade::Hard);
+        assert_eq!(
+            suggest_grade(&diff("ribosome", "mitochondria")),
+            Grade::Again
+        );
+    }
+}
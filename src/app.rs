@@ -1,15 +1,32 @@
+use crate::card::{Card, CardKind};
+use crate::config::Config;
+use crate::custom_study::CustomStudy;
+use crate::deck::Deck;
+use crate::external_editor;
+use crate::forecast;
+use crate::fsrs::{self, FsrsParameters, Optimization};
 use crate::modes::edit::EditApp;
+use crate::modes::occlusion::OcclusionApp;
 use crate::modes::selection::SelectionApp;
 use crate::modes::testing::TestingApp;
+use crate::occlusion;
+use crate::popup::Popup;
+use crate::reschedule::{self, Reschedule};
+use crate::scheduler::SchedulerSettings;
+use crate::spell::SpellChecker;
+use crate::study_queue;
 use crate::term;
 
+use chrono::Utc;
 use color_eyre::eyre::Context;
 use color_eyre::eyre::Result;
-use crossterm::event::{Event, KeyEventKind};
+use crossterm::event::{Event, KeyEvent, KeyEventKind};
 use ratatui::{prelude::*, widgets::*};
 use std::io::Error;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
 use std::time::Duration;
 
 pub struct App {
@@ -17,9 +34,60 @@ pub struct App {
     selection_mode: SelectionApp,
     edit_mode: EditApp,
     testing_mode: TestingApp,
+    occlusion_mode: OcclusionApp,
     cards_path: Option<PathBuf>,
+    decks: Vec<Deck>,
+    // Index in decks of the deck opened in the card selection or tested
+    current_deck: usize,
+    current_popup: Option<Popup<AppPopupTypes>>,
+    // Text of the last external edit that could not be parsed, kept to edit it again
+    unparsed_editor_text: Option<String>,
+    // Result of the fitting of FSRS running on another thread for the current deck
+    optimization: Option<Receiver<Option<Optimization>>>,
 }
 
+enum AppPopupTypes {
+    CardParseError,
+    EditorError,
+    InvalidConfig,
+    InvalidDictionary,
+    InvalidDeck,
+    SaveFailure,
+    Optimizing, // Shown while the fitting runs, without buttons
+    Optimization,
+    InvalidImage,
+    ReplayLog,
+    LogReplayed,
+    Forecast,
+    // Kind of bulk rescheduling, then its number of days
+    Reschedule,
+    RescheduleDays(u16),
+    Rescheduled,
+    // Steps of a custom study session: its kind, then what it needs, then
+    // whether it changes the schedules
+    CustomStudy,
+    CustomStudyTag(Vec<String>, usize), // All the tags, and the first one shown
+    CustomStudyDays,
+    CustomStudyCount,
+    CustomStudyScheduling(CustomStudy),
+}
+
+const SCHEDULING_QUESTION: &str =
+    "Should the grades of this session change the schedules ?\nNO to cram before an exam.";
+
+// Days shown by the forecast popup, and the width of its bars
+const FORECAST_DAYS: usize = 10;
+const FORECAST_WIDTH: usize = 20;
+
+// Days the reschedulings can be done over
+const RESCHEDULE_DAYS: [u32; 4] = [3, 7, 14, 30];
+
+// Choices given by the custom study popups
+const CUSTOM_STUDY_DAYS: [u32; 3] = [1, 7, 30];
+const CUSTOM_STUDY_COUNTS: [usize; 4] = [10, 20, 50, 100];
+// Tags shown at once, MORE shows the next ones
+const CUSTOM_STUDY_TAGS: usize = 4;
+
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum Mode {
     //The data structure that the app uses to decide wich mode it's in
@@ -28,6 +96,7 @@ pub enum Mode {
     SelectionCard,
     Edit,
     Testing,
+    Occlusion, // Editor of the masks of an image occlusion card
     Quit,
 }
 
@@ -36,29 +105,94 @@ pub enum Message {
     //Data structure that modes use to comunicate to the main app actions
     ChangeMode(Mode),
     Delete(u32),
+    AddCard,
+    SaveFailed(String),
+    SwitchScheduler,
+    OptimizeScheduler,
+    ReplayLog,
+    ShowForecast,
+    Reschedule,
+    CustomStudy,
+    StartCustomStudy(CustomStudy, bool), // Whether the grades change the schedules
+    OpenExternalEditor,
     Nothing,
 }
 
 impl App {
-    fn get_cards_path() -> Option<PathBuf> {
-        todo!()
+    pub fn get_cards_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| Deck::decks_path(&dir.join("balatui")))
     }
 
     pub fn new() -> Result<App> {
-        let read_cards_path: Option<String> = None; //Read cards path form file
+        // A broken configuration should not prevent studying, the defaults
+        // are used and the error is shown
+        let (config, mut current_popup) = match Config::load() {
+            Ok(config) => (config, None),
+            Err(error) => (
+                Config::default(),
+                Some(Popup::new(
+                    AppPopupTypes::InvalidConfig,
+                    format!("The configuration could not be loaded :\n{:#}", error),
+                    vec![String::from("OK")],
+                )),
+            ),
+        };
+        let spell_checker = match config.spell.dictionary.as_ref() {
+            Some(dictionary) => match SpellChecker::load(dictionary) {
+                Ok(spell_checker) => Some(spell_checker),
+                Err(error) => {
+                    current_popup = Some(Popup::new(
+                        AppPopupTypes::InvalidDictionary,
+                        format!("The dictionary could not be loaded :\n{:#}", error),
+                        vec![String::from("OK")],
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        let cards_path = App::get_cards_path();
+        let (mut decks, errors) = match cards_path.as_deref().map(Deck::load_all) {
+            Some(Ok(loaded)) => loaded,
+            Some(Err(error)) => (Vec::new(), vec![error]),
+            None => (Vec::new(), Vec::new()),
+        };
+        if !errors.is_empty() {
+            let errors: Vec<String> = errors.iter().map(|error| format!("{:#}", error)).collect();
+            current_popup = Some(Popup::new(
+                AppPopupTypes::InvalidDeck,
+                format!("Some decks could not be loaded :\n{}", errors.join("\n")),
+                vec![String::from("OK")],
+            ));
+        }
+        // There is always a deck to add the first cards to
+        if decks.is_empty() {
+            let decks_path = cards_path.clone().unwrap_or_default();
+            decks.push(Deck::placeholder(&decks_path));
+        }
+        // Asks the terminal which images it can show, before any event is read
+        let picker = occlusion::terminal_picker();
+        let mut selection_mode = SelectionApp::new();
+        selection_mode.set_elements(App::deck_elements(&decks));
         Ok(App {
             mode: Mode::SelectionDeck,
-            selection_mode: SelectionApp::new(),
-            edit_mode: EditApp::new(),
-            testing_mode: TestingApp::new(),
-            cards_path: None, //To rework
+            selection_mode,
+            edit_mode: EditApp::new(&config, spell_checker),
+            testing_mode: TestingApp::new(picker),
+            occlusion_mode: OcclusionApp::new(picker),
+            cards_path,
+            decks,
+            current_deck: 0,
+            current_popup,
+            unparsed_editor_text: None,
+            optimization: None,
         })
     }
 
     pub fn run(&mut self, terminal: &mut Terminal<impl Backend>) -> Result<()> {
         while self.is_running() {
             self.draw(terminal)?;
-            self.handle_events()?;
+            self.handle_events(terminal)?;
         }
         Ok(())
     }
@@ -74,10 +208,18 @@ impl App {
         Ok(())
     }
 
-    fn handle_events(&mut self) -> Result<()> {
+    fn handle_events(&mut self, terminal: &mut Terminal<impl Backend>) -> Result<()> {
         let timeout = Duration::from_secs_f64(1.0 / 50.0);
         let message;
+        self.check_optimization();
         match term::next_event(timeout)? {
+            // The deck can't change while FSRS is being fitted to it
+            Some(Event::Key(_)) if self.optimization.is_some() => message = Message::Nothing,
+            Some(Event::Key(key))
+                if key.kind == KeyEventKind::Press && self.current_popup.is_some() =>
+            {
+                message = self.handle_popup_key_press(key);
+            }
             Some(Event::Key(key)) if key.kind == KeyEventKind::Press => {
                 //every mode handles it's own key presses
                 match self.mode {
@@ -89,19 +231,598 @@ impl App {
                     }
                     Mode::Edit => message = self.edit_mode.handle_key_press(key),
                     Mode::Testing => message = self.testing_mode.handle_key_press(key),
+                    Mode::Occlusion => message = self.occlusion_mode.handle_key_press(key),
                     Mode::Quit => message = Message::Nothing,
                 }
             }
-            _ => message = Message::Nothing,
+            _ => {
+                if self.mode == Mode::Testing && self.current_popup.is_none() {
+                    self.testing_mode.tick(Utc::now());
+                }
+                message = Message::Nothing
+            }
         }
         match message {
-            Message::ChangeMode(mode) => self.mode = mode,
+            Message::ChangeMode(mode) => self.change_mode(mode),
+            Message::OpenExternalEditor => {
+                if self.mode == Mode::SelectionCard {
+                    let card = self.selected_card().clone();
+                    self.edit_mode.set_card(card);
+                }
+                if let (Mode::Testing, Some(card)) = (self.mode, self.testing_mode.edited_card()) {
+                    self.edit_mode.set_card(card.clone());
+                }
+                let text = self.edit_mode.card().to_editor_text();
+                self.open_external_editor(terminal, text)?;
+            }
+            Message::AddCard => {
+                let id = self.decks[self.current_deck].add_card(Card::default());
+                let card = self.decks[self.current_deck].card(id).unwrap().clone();
+                self.edit_mode.set_card(card);
+                self.mode = Mode::Edit;
+            }
+            Message::Delete(index) => self.delete(index as usize),
+            Message::SaveFailed(error) => {
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::SaveFailure,
+                    format!("The deck could not be saved :\n{}", error),
+                    vec![String::from("OK")],
+                ));
+            }
+            Message::SwitchScheduler => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                let deck = &mut self.decks[self.current_deck];
+                deck.scheduler = match deck.scheduler {
+                    SchedulerSettings::Sm2 => SchedulerSettings::Fsrs(FsrsParameters::default()),
+                    SchedulerSettings::Fsrs(_) => SchedulerSettings::Sm2,
+                };
+                self.save_current_deck();
+                self.show_decks();
+            }
+            Message::OptimizeScheduler => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                self.optimize_scheduler();
+            }
+            Message::ReplayLog => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                let deck = &self.decks[self.current_deck];
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::ReplayLog,
+                    format!(
+                        "Rebuild the schedules of {} by replaying its {} reviews with {} ?\n\
+                         The reviews moved by SPREAD, POSTPONE or ADVANCE are due again as graded.",
+                        deck.name,
+                        deck.review_log.reviews.len(),
+                        deck.scheduler.name()
+                    ),
+                    vec![String::from("YES"), String::from("NO")],
+                ));
+            }
+            Message::ShowForecast => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                let deck = &self.decks[self.current_deck];
+                let counts = forecast::forecast(deck, Utc::now(), FORECAST_DAYS);
+                let largest = counts.iter().copied().max().unwrap_or(0);
+                let mut content = format!("Reviews due in {}\n", deck.name);
+                for (day, count) in counts.into_iter().enumerate() {
+                    content.push('\n');
+                    content.push_str(&forecast::forecast_line(
+                        day,
+                        count,
+                        largest,
+                        FORECAST_WIDTH,
+                    ));
+                }
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::Forecast,
+                    content,
+                    vec![String::from("OK")],
+                ));
+            }
+            Message::Reschedule => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::Reschedule,
+                    String::from(
+                        "Reschedule the reviews :\nSPREAD the overdue ones over the next days, \
+                         POSTPONE those due before a break, ADVANCE those due after an exam",
+                    ),
+                    vec![
+                        String::from("SPREAD"),
+                        String::from("POSTPONE"),
+                        String::from("ADVANCE"),
+                        String::from("CANCEL"),
+                    ],
+                ));
+            }
+            Message::CustomStudy => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::CustomStudy,
+                    String::from("Custom study, which cards ?"),
+                    vec![
+                        String::from("TAG"),
+                        String::from("FORGOTTEN"),
+                        String::from("RANDOM"),
+                        String::from("AHEAD"),
+                        String::from("CANCEL"),
+                    ],
+                ));
+            }
+            Message::StartCustomStudy(study, reschedule) => {
+                let deck = std::mem::take(&mut self.decks[self.current_deck]);
+                self.testing_mode
+                    .start_custom(deck, &study, reschedule, Utc::now());
+                self.mode = Mode::Testing;
+            }
+            Message::Nothing => {}
+        }
+        Ok(())
+    }
+
+    //Moves the data between the modes when going from one to the other
+    fn change_mode(&mut self, mode: Mode) {
+        match (self.mode, mode) {
+            (Mode::SelectionDeck, Mode::SelectionCard) => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                self.selection_mode.set_cursor_position(0);
+                self.show_cards();
+            }
+            (Mode::SelectionCard, Mode::SelectionDeck) => self.show_decks(),
+            (Mode::SelectionCard, Mode::Edit) => {
+                let card = self.selected_card().clone();
+                self.edit_mode.set_card(card);
+            }
+            (Mode::Edit, Mode::SelectionCard) => {
+                let card = self.edit_mode.card().clone();
+                self.store_card(card);
+                self.show_cards();
+            }
+            (Mode::SelectionCard, Mode::Occlusion) if !self.open_mask_editor() => return,
+            (Mode::Occlusion, Mode::SelectionCard) => {
+                let card = self.occlusion_mode.card().clone();
+                self.store_card(card);
+                self.show_cards();
+            }
+            (Mode::SelectionDeck, Mode::Testing) => {
+                self.current_deck = self.selection_mode.cursor_position() as usize;
+                let deck = std::mem::take(&mut self.decks[self.current_deck]);
+                self.testing_mode.start(deck, Utc::now());
+            }
+            (Mode::Testing, Mode::SelectionDeck) => {
+                if let Some(deck) = self.testing_mode.finish() {
+                    self.decks[self.current_deck] = deck;
+                }
+                self.show_decks();
+            }
+            _ => {}
+        }
+        self.mode = mode;
+    }
+
+    //Opens the selected card in the mask editor, false with a popup saying
+    //why when it is not an image occlusion card or its image can't be loaded
+    fn open_mask_editor(&mut self) -> bool {
+        let card = self.selected_card().clone();
+        let error = if card.kind != CardKind::ImageOcclusion {
+            format!(
+                "Only the masks of \"{}\" cards can be edited.",
+                CardKind::ImageOcclusion.name()
+            )
+        } else {
+            let path = self.decks[self.current_deck].image_path(&card);
+            match occlusion::load_image(&path) {
+                Ok(image) => {
+                    self.occlusion_mode.set_card(card, image);
+                    return true;
+                }
+                Err(error) => format!("The image could not be loaded :\n{:#}", error),
+            }
+        };
+        self.current_popup = Some(Popup::new(
+            AppPopupTypes::InvalidImage,
+            error,
+            vec![String::from("OK")],
+        ));
+        false
+    }
+
+    //Fits the FSRS weights of the current deck to its review log, the deck
+    //uses FSRS afterwards. The fitting takes a while on a long log, it runs on
+    //another thread and a popup waits for it.
+    fn optimize_scheduler(&mut self) {
+        let deck = &self.decks[self.current_deck];
+        let weights = match &deck.scheduler {
+            SchedulerSettings::Fsrs(parameters) => parameters.weights,
+            SchedulerSettings::Sm2 => FsrsParameters::default().weights,
+        };
+        let reviews = deck.review_log.reviews.clone();
+        self.current_popup = Some(Popup::new(
+            AppPopupTypes::Optimizing,
+            format!(
+                "Fitting FSRS to the {} reviews of {}...",
+                reviews.len(),
+                deck.name
+            ),
+            Vec::new(),
+        ));
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Nobody waits anymore if the app was quit meanwhile
+            let _ = sender.send(fsrs::optimize(&weights, &reviews));
+        });
+        self.optimization = Some(receiver);
+    }
+
+    //Uses the fitted weights once the fitting thread is done
+    fn check_optimization(&mut self) {
+        let Some(receiver) = self.optimization.as_ref() else {
+            return;
+        };
+        let deck = &mut self.decks[self.current_deck];
+        let content = match receiver.try_recv() {
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => String::from("The fitting of FSRS failed."),
+            Ok(None) => String::from("Not enough reviews to optimize the scheduler yet."),
+            Ok(Some(optimization)) => {
+                let mut parameters = match &deck.scheduler {
+                    SchedulerSettings::Fsrs(parameters) => parameters.clone(),
+                    SchedulerSettings::Sm2 => FsrsParameters::default(),
+                };
+                parameters.weights = optimization.weights;
+                deck.scheduler = SchedulerSettings::Fsrs(parameters);
+                format!(
+                    "FSRS fitted to {} reviews.\nLog loss : {:.4} -> {:.4}",
+                    optimization.predictions, optimization.loss_before, optimization.loss_after
+                )
+            }
+        };
+        self.optimization = None;
+        self.current_popup = Some(Popup::new(
+            AppPopupTypes::Optimization,
+            content,
+            vec![String::from("OK")],
+        ));
+        self.save_current_deck();
+        self.show_decks();
+    }
+
+    fn deck_elements(decks: &[Deck]) -> Vec<String> {
+        decks
+            .iter()
+            .map(|deck| {
+                let counts = study_queue::queue_counts(deck, Utc::now());
+                format!(
+                    "{}\n{} cards, {}\nNew {}  Learn {}  Due {}",
+                    deck.name,
+                    deck.cards.len(),
+                    deck.scheduler.name(),
+                    counts.new,
+                    counts.learning,
+                    counts.due
+                )
+            })
+            .collect()
+    }
+
+    fn show_decks(&mut self) {
+        self.selection_mode
+            .set_elements(App::deck_elements(&self.decks));
+        self.selection_mode
+            .set_cursor_position(self.current_deck as u32);
+    }
+
+    fn show_cards(&mut self) {
+        let elements = self.decks[self.current_deck]
+            .cards
+            .iter()
+            .map(|card| {
+                let first_line = card.front.lines().next().unwrap_or("");
+                if card.suspended {
+                    format!("{} (suspended)", first_line)
+                } else {
+                    first_line.to_string()
+                }
+            })
+            .collect();
+        self.selection_mode.set_elements(elements);
+    }
+
+    fn selected_card(&self) -> &Card {
+        &self.decks[self.current_deck].cards[self.selection_mode.cursor_position() as usize]
+    }
+
+    //Replaces the card with the same id in the current deck and saves it
+    fn store_card(&mut self, card: Card) {
+        let deck = &mut self.decks[self.current_deck];
+        if let Some(stored) = deck.card_mut(card.id) {
+            *stored = card;
+        }
+        self.save_current_deck();
+    }
+
+    fn save_current_deck(&mut self) {
+        if let Err(error) = self.decks[self.current_deck].save() {
+            self.current_popup = Some(Popup::new(
+                AppPopupTypes::SaveFailure,
+                format!("The deck could not be saved :\n{:#}", error),
+                vec![String::from("OK")],
+            ));
+        }
+    }
+
+    fn delete(&mut self, index: usize) {
+        match self.mode {
+            Mode::SelectionCard => {
+                self.decks[self.current_deck].cards.remove(index);
+                self.save_current_deck();
+                self.show_cards();
+            }
+            Mode::SelectionDeck => {
+                let deck = self.decks.remove(index);
+                if let Err(error) = deck.delete_file() {
+                    self.current_popup = Some(Popup::new(
+                        AppPopupTypes::SaveFailure,
+                        format!("The deck could not be deleted :\n{:#}", error),
+                        vec![String::from("OK")],
+                    ));
+                }
+                if self.decks.is_empty() {
+                    let decks_path = self.cards_path.clone().unwrap_or_default();
+                    self.decks.push(Deck::placeholder(&decks_path));
+                }
+                self.current_deck = index.min(self.decks.len() - 1);
+                self.show_decks();
+            }
             _ => {}
         }
+    }
+
+    fn handle_popup_key_press(&mut self, key: KeyEvent) -> Message {
+        let popup = self.current_popup.as_mut().unwrap();
+        let result = popup.handle_key_press(key);
+        if result == popup.number_of_buttons {
+            return Message::Nothing;
+        }
+        let popup_type = self.current_popup.take().unwrap().popup_type;
+        match popup_type {
+            AppPopupTypes::CardParseError => {
+                // First button edits the text again, the other discards it
+                if result == 0 {
+                    return Message::OpenExternalEditor;
+                }
+                self.unparsed_editor_text = None;
+            }
+            AppPopupTypes::ReplayLog => {
+                if result == 0 {
+                    let rebuilt = self.decks[self.current_deck].replay_log();
+                    self.current_popup = Some(Popup::new(
+                        AppPopupTypes::LogReplayed,
+                        format!("The schedules of {} items were rebuilt.", rebuilt),
+                        vec![String::from("OK")],
+                    ));
+                    self.save_current_deck();
+                    self.show_decks();
+                }
+            }
+            AppPopupTypes::Reschedule => {
+                let content = match result {
+                    0 => "Spread the overdue reviews over :",
+                    1 => "Postpone the reviews due in the next days by :",
+                    2 => "The exam is in :",
+                    _ => return Message::Nothing,
+                };
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::RescheduleDays(result),
+                    String::from(content),
+                    RESCHEDULE_DAYS
+                        .iter()
+                        .map(|days| format!("{} DAYS", days))
+                        .chain(std::iter::once(String::from("CANCEL")))
+                        .collect(),
+                ));
+            }
+            AppPopupTypes::RescheduleDays(kind) => {
+                let Some(&days) = RESCHEDULE_DAYS.get(result as usize) else {
+                    return Message::Nothing;
+                };
+                let now = Utc::now();
+                let change = match kind {
+                    0 => Reschedule::Spread(days),
+                    1 => Reschedule::Postpone(days),
+                    _ => Reschedule::Advance(now + chrono::Duration::days(days as i64)),
+                };
+                let moved = reschedule::reschedule(&mut self.decks[self.current_deck], change, now);
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::Rescheduled,
+                    format!("{} reviews were rescheduled.", moved),
+                    vec![String::from("OK")],
+                ));
+                self.save_current_deck();
+                self.show_decks();
+            }
+            // The last button of the custom study popups cancels it
+            AppPopupTypes::CustomStudy => {
+                if result < 4 {
+                    self.current_popup = Some(self.custom_study_popup(result))
+                }
+            }
+            AppPopupTypes::CustomStudyTag(tags, first) => {
+                let result = result as usize;
+                let shown = tags.len().saturating_sub(first).min(CUSTOM_STUDY_TAGS);
+                if result < shown {
+                    let tag = tags[first + result].clone();
+                    self.ask_custom_study_scheduling(CustomStudy::Tag(tag));
+                } else if result == shown && tags.len() > CUSTOM_STUDY_TAGS {
+                    let next = first + CUSTOM_STUDY_TAGS;
+                    let next = if next < tags.len() { next } else { 0 };
+                    self.current_popup = Some(custom_study_tag_popup(tags, next));
+                }
+            }
+            AppPopupTypes::CustomStudyDays => {
+                if let Some(days) = CUSTOM_STUDY_DAYS.get(result as usize) {
+                    self.ask_custom_study_scheduling(CustomStudy::Forgotten(*days));
+                }
+            }
+            AppPopupTypes::CustomStudyCount => {
+                if let Some(count) = CUSTOM_STUDY_COUNTS.get(result as usize) {
+                    self.ask_custom_study_scheduling(CustomStudy::Random(*count));
+                }
+            }
+            AppPopupTypes::CustomStudyScheduling(study) => {
+                if result < 2 {
+                    return Message::StartCustomStudy(study, result == 1);
+                }
+            }
+            AppPopupTypes::EditorError
+            | AppPopupTypes::InvalidConfig
+            | AppPopupTypes::InvalidDictionary
+            | AppPopupTypes::InvalidDeck
+            | AppPopupTypes::SaveFailure
+            | AppPopupTypes::Optimizing
+            | AppPopupTypes::Optimization
+            | AppPopupTypes::InvalidImage
+            | AppPopupTypes::LogReplayed
+            | AppPopupTypes::Forecast
+            | AppPopupTypes::Rescheduled => {}
+        }
+        Message::Nothing
+    }
+
+    //Popup asking what the custom study of the given kind needs
+    fn custom_study_popup(&self, kind: u16) -> Popup<AppPopupTypes> {
+        match kind {
+            0 => custom_study_tag_popup(self.decks[self.current_deck].tags(), 0),
+            1 => Popup::new(
+                AppPopupTypes::CustomStudyDays,
+                String::from("Study the cards forgotten in the last :"),
+                CUSTOM_STUDY_DAYS
+                    .iter()
+                    .map(|days| format!("{} DAYS", days))
+                    .chain(std::iter::once(String::from("CANCEL")))
+                    .collect(),
+            ),
+            2 => Popup::new(
+                AppPopupTypes::CustomStudyCount,
+                String::from("Number of random cards to study :"),
+                CUSTOM_STUDY_COUNTS
+                    .iter()
+                    .map(|count| count.to_string())
+                    .chain(std::iter::once(String::from("CANCEL")))
+                    .collect(),
+            ),
+            _ => Popup::new(
+                AppPopupTypes::CustomStudyScheduling(CustomStudy::AheadOfSchedule),
+                String::from(SCHEDULING_QUESTION),
+                scheduling_buttons(),
+            ),
+        }
+    }
+
+    fn ask_custom_study_scheduling(&mut self, study: CustomStudy) {
+        self.current_popup = Some(Popup::new(
+            AppPopupTypes::CustomStudyScheduling(study),
+            String::from(SCHEDULING_QUESTION),
+            scheduling_buttons(),
+        ));
+    }
+
+    //Suspends the TUI, lets the user edit the card in $VISUAL/$EDITOR then
+    //imports the result back in the card
+    fn open_external_editor(
+        &mut self,
+        terminal: &mut Terminal<impl Backend>,
+        text: String,
+    ) -> Result<()> {
+        let text = self.unparsed_editor_text.take().unwrap_or(text);
+        term::restore()?;
+        let edited = external_editor::edit_text(&text);
+        // The returned terminal is not needed, ours still writes to the same stdout
+        term::init()?;
+        terminal.clear()?;
+        match edited {
+            Ok(edited) => {
+                match Card::from_editor_text(&edited) {
+                    Ok(parsed) => {
+                        let edited_card = self.edit_mode.card();
+                        let card = Card {
+                            id: edited_card.id,
+                            masks: edited_card.masks.clone(),
+                            schedules: edited_card.schedules.clone(),
+                            ..parsed
+                        };
+                        self.edit_mode.set_card(card.clone());
+                        // Edited from the card selection, the card is saved right away
+                        if self.mode == Mode::SelectionCard {
+                            self.store_card(card);
+                            self.show_cards();
+                        } else if self.mode == Mode::Testing {
+                            if let Err(error) = self.testing_mode.store_edited_card(card) {
+                                self.current_popup = Some(Popup::new(
+                                    AppPopupTypes::SaveFailure,
+                                    format!("The deck could not be saved :\n{:#}", error),
+                                    vec![String::from("OK")],
+                                ));
+                            }
+                        }
+                    }
+                    Err(error) => {
+                        self.unparsed_editor_text = Some(edited);
+                        self.current_popup = Some(Popup::new(
+                            AppPopupTypes::CardParseError,
+                            format!("The card could not be read :\n{}", error),
+                            vec![String::from("EDIT AGAIN"), String::from("DISCARD")],
+                        ));
+                    }
+                }
+            }
+            Err(error) => {
+                self.current_popup = Some(Popup::new(
+                    AppPopupTypes::EditorError,
+                    format!("The editor could not be used :\n{}", error),
+                    vec![String::from("OK")],
+                ));
+            }
+        }
         Ok(())
     }
 }
 
+fn scheduling_buttons() -> Vec<String> {
+    vec![
+        String::from("NO"),
+        String::from("YES"),
+        String::from("CANCEL"),
+    ]
+}
+
+//Popup with a page of the tags starting at the given one, MORE goes to the
+//next page
+fn custom_study_tag_popup(tags: Vec<String>, first: usize) -> Popup<AppPopupTypes> {
+    if tags.is_empty() {
+        return Popup::new(
+            AppPopupTypes::CustomStudyTag(tags, 0),
+            String::from("No card of this deck has a tag."),
+            vec![String::from("OK")],
+        );
+    }
+    let mut buttons: Vec<String> = tags
+        .iter()
+        .skip(first)
+        .take(CUSTOM_STUDY_TAGS)
+        .cloned()
+        .collect();
+    if tags.len() > CUSTOM_STUDY_TAGS {
+        buttons.push(String::from("MORE"));
+    }
+    buttons.push(String::from("CANCEL"));
+    Popup::new(
+        AppPopupTypes::CustomStudyTag(tags, first),
+        String::from("Study the cards with the tag :"),
+        buttons,
+    )
+}
+
 /// Implement Widget for &App rather than for App as we would otherwise have to clone or copy the
 /// entire app state on every frame.
 impl Widget for &App {
@@ -115,6 +836,17 @@ impl Widget for &App {
 
         self.render_selected_mode(tab, buf);
         //self.render_bottom_bar(bottom_bar, buf);
+
+        if let Some(popup) = self.current_popup.as_ref() {
+            // Long popups like the forecast get the rows they need
+            let mut area = Popup::<AppPopupTypes>::make_centered_rectangle_area(50, 40, tab);
+            let height = popup.height().min(tab.height);
+            if height > area.height {
+                area.y = tab.y + (tab.height - height) / 2;
+                area.height = height;
+            }
+            popup.render(area, buf);
+        }
     }
 }
 
@@ -125,7 +857,10 @@ impl App {
 
     fn render_selected_mode(&self, area: Rect, buf: &mut Buffer) {
         match self.mode {
-            Mode::SelectionDeck => self.selection_mode.render(area, buf),
+            Mode::SelectionDeck | Mode::SelectionCard => self.selection_mode.render(area, buf),
+            Mode::Edit => self.edit_mode.render(area, buf),
+            Mode::Testing => self.testing_mode.render(area, buf),
+            Mode::Occlusion => self.occlusion_mode.render(area, buf),
             _ => {}
         }
     }
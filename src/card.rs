@@ -0,0 +1,417 @@
+// A card is the unit of content of balatui: a front (the question) and a back
+// (the answer), both written in LaTeX flavoured text. A card is reviewed as one
+// or several items, e.g. one per cloze number, each with its own schedule.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cloze;
+use crate::occlusion::Mask;
+use crate::scheduler::Schedule;
+
+// Every field starts with a line "%% <field name>" in the text given to the
+// external editor. "%" starts a LaTeX comment so the file stays valid LaTeX.
+const FIELD_SEPARATOR: &str = "%% ";
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Card {
+    pub id: u64, // Unique in its deck, given by Deck::add_card
+    pub front: String,
+    pub back: String,
+    #[serde(default)]
+    pub kind: CardKind,
+    // Also reviewed from the back to the front, as a second item (ordinal 1)
+    #[serde(default)]
+    pub reverse: bool,
+    // Words without spaces, e.g. "leech" (see leech.rs)
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Left out of the reviews until unsuspended
+    #[serde(default)]
+    pub suspended: bool,
+    // Wrong answers of a multiple choice card
+    #[serde(default)]
+    pub choices: Vec<String>,
+    // Image of an image occlusion card, relative to the decks directory unless
+    // absolute, and the masks hiding its parts
+    #[serde(default)]
+    pub image: String,
+    #[serde(default)]
+    pub masks: Vec<Mask>,
+    // Indexed by the ordinal of the items
+    #[serde(default)]
+    pub schedules: Vec<Schedule>,
+}
+
+// One reviewed item of a card, its ordinal is 0 for a basic card (1 for its
+// reverse), n - 1 for the cloze number n and the index of the mask for an
+// image occlusion. The items of a card are siblings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ReviewItem {
+    pub card_id: u64,
+    pub ordinal: u32,
+}
+
+// How the card is reviewed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardKind {
+    #[default]
+    Basic,
+    TypeAnswer,     // The back is typed then compared to the expected one
+    Cloze,          // The front contains clozes, see cloze.rs
+    MultipleChoice, // The back is picked among distractors, see multiple_choice.rs
+    OrderedList,    // The lines of the back are put in order, see ordered_list.rs
+    ImageOcclusion, // A mask of the image is to be recalled, see occlusion.rs
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CardParseError {
+    DuplicateField(usize, String),
+    MissingField(String),
+    StrayText(usize),
+    UnknownKind(usize, String),
+    InvalidYesNo(usize, String, String), // Line, field and value
+}
+
+impl fmt::Display for CardParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardParseError::DuplicateField(line, name) => {
+                write!(f, "Line {} : field \"{}\" is given twice", line, name)
+            }
+            CardParseError::MissingField(name) => write!(f, "The field \"{}\" is missing", name),
+            CardParseError::StrayText(line) => write!(
+                f,
+                "Line {} : text before the first \"{}<field>\" separator",
+                line, FIELD_SEPARATOR
+            ),
+            CardParseError::UnknownKind(line, name) => write!(
+                f,
+                "Line {} : unknown kind \"{}\", expected one of {}",
+                line,
+                name,
+                CardKind::ALL.map(CardKind::name).join(", ")
+            ),
+            CardParseError::InvalidYesNo(line, field, value) => write!(
+                f,
+                "Line {} : {} must be \"yes\" or \"no\", not \"{}\"",
+                line, field, value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CardParseError {}
+
+impl CardKind {
+    pub const ALL: [CardKind; 6] = [
+        CardKind::Basic,
+        CardKind::TypeAnswer,
+        CardKind::Cloze,
+        CardKind::MultipleChoice,
+        CardKind::OrderedList,
+        CardKind::ImageOcclusion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CardKind::Basic => "basic",
+            CardKind::TypeAnswer => "type answer",
+            CardKind::Cloze => "cloze",
+            CardKind::MultipleChoice => "multiple choice",
+            CardKind::OrderedList => "ordered list",
+            CardKind::ImageOcclusion => "image occlusion",
+        }
+    }
+}
+
+impl Card {
+    pub fn new(front: String, back: String) -> Card {
+        Card {
+            id: 0,
+            front,
+            back,
+            kind: CardKind::Basic,
+            reverse: false,
+            tags: Vec::new(),
+            suspended: false,
+            choices: Vec::new(),
+            image: String::new(),
+            masks: Vec::new(),
+            schedules: Vec::new(),
+        }
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|other| other == tag)
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        if !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+        }
+    }
+
+    //Ordinals of the items reviewed, a cloze card without cloze has none, nor
+    //has an image occlusion card without mask
+    pub fn ordinals(&self) -> Vec<u32> {
+        match self.kind {
+            CardKind::Basic | CardKind::TypeAnswer if self.reverse => vec![0, 1],
+            CardKind::Cloze => cloze::numbers(&self.front)
+                .into_iter()
+                .filter(|number| *number > 0)
+                .map(|number| number - 1)
+                .collect(),
+            CardKind::ImageOcclusion => (0..self.masks.len() as u32).collect(),
+            _ => vec![0],
+        }
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = (ReviewItem, Schedule)> + '_ {
+        self.ordinals().into_iter().map(|ordinal| {
+            let item = ReviewItem {
+                card_id: self.id,
+                ordinal,
+            };
+            (item, self.schedule(ordinal))
+        })
+    }
+
+    //Items never reviewed have the default schedule
+    pub fn schedule(&self, ordinal: u32) -> Schedule {
+        self.schedules
+            .get(ordinal as usize)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn set_schedule(&mut self, ordinal: u32, schedule: Schedule) {
+        let index = ordinal as usize;
+        if self.schedules.len() <= index {
+            self.schedules.resize(index + 1, Schedule::default());
+        }
+        self.schedules[index] = schedule;
+    }
+
+    fn is_reversed(&self, ordinal: u32) -> bool {
+        self.reverse && matches!(self.kind, CardKind::Basic | CardKind::TypeAnswer) && ordinal == 1
+    }
+
+    //Text shown before the answer is revealed
+    pub fn question(&self, ordinal: u32) -> String {
+        match self.kind {
+            _ if self.is_reversed(ordinal) => self.back.clone(),
+            CardKind::Cloze => cloze::question(&self.front, ordinal + 1),
+            _ => self.front.clone(),
+        }
+    }
+
+    //Text shown in place of the question once the answer is revealed
+    pub fn revealed_question(&self, ordinal: u32) -> String {
+        match self.kind {
+            CardKind::Cloze => cloze::answer(&self.front),
+            _ => self.question(ordinal),
+        }
+    }
+
+    //Text shown under the question once revealed, the one to type for a
+    //"type answer" card
+    pub fn answer(&self, ordinal: u32) -> &str {
+        if self.is_reversed(ordinal) {
+            &self.front
+        } else {
+            &self.back
+        }
+    }
+
+    //Text written to the temporary file opened in $VISUAL/$EDITOR
+    pub fn to_editor_text(&self) -> String {
+        format!(
+            concat!(
+                "{sep}kind\n{}\n{sep}reverse\n{}\n{sep}tags\n{}\n{sep}suspended\n{}\n",
+                "{sep}front\n{}\n{sep}back\n{}\n{sep}choices\n{}\n{sep}image\n{}\n"
+            ),
+            self.kind.name(),
+            yes_no(self.reverse),
+            self.tags.join(" "),
+            yes_no(self.suspended),
+            self.front,
+            self.back,
+            self.choices.join("\n"),
+            self.image,
+            sep = FIELD_SEPARATOR
+        )
+    }
+
+    //Parse back the text written by to_editor_text (and modified by the user).
+    //Only the front and back are required, the other fields take their default
+    //value without them. The id, masks and schedules are not in the text. A
+    //line is a separator only when it names a field, "%% " also starts the
+    //comments of LaTeX and Matlab.
+    pub fn from_editor_text(text: &str) -> Result<Card, CardParseError> {
+        const FIELD_NAMES: [&str; 8] = [
+            "front",
+            "back",
+            "kind",
+            "reverse",
+            "tags",
+            "suspended",
+            "choices",
+            "image",
+        ];
+        let mut fields: [Option<Vec<&str>>; 8] = Default::default();
+        let mut field_lines = [0; 8];
+        let mut current_field: Option<usize> = None;
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let field = line
+                .strip_prefix(FIELD_SEPARATOR)
+                .and_then(|name| FIELD_NAMES.iter().position(|field| *field == name.trim()));
+            if let Some(field) = field {
+                let name = FIELD_NAMES[field];
+                if fields[field].is_some() {
+                    return Err(CardParseError::DuplicateField(
+                        line_number,
+                        name.to_string(),
+                    ));
+                }
+                fields[field] = Some(Vec::new());
+                field_lines[field] = line_number;
+                current_field = Some(field);
+            } else if let Some(field) = current_field {
+                fields[field].as_mut().unwrap().push(line);
+            } else if !line.trim().is_empty() {
+                return Err(CardParseError::StrayText(line_number));
+            }
+        }
+        let [front, back, kind, reverse, tags, suspended, choices, image] = fields;
+        let front = front.ok_or(CardParseError::MissingField(String::from("front")))?;
+        let back = back.ok_or(CardParseError::MissingField(String::from("back")))?;
+        let kind = match kind {
+            Some(kind) => {
+                let name = Card::join_field(kind);
+                let name = name.trim();
+                CardKind::ALL
+                    .into_iter()
+                    .find(|kind| kind.name() == name)
+                    .ok_or_else(|| CardParseError::UnknownKind(field_lines[2], name.to_string()))?
+            }
+            None => CardKind::default(),
+        };
+        let reverse = parse_yes_no(reverse, "reverse", field_lines[3])?.unwrap_or(false);
+        let suspended = parse_yes_no(suspended, "suspended", field_lines[5])?.unwrap_or(false);
+        let tags = tags.map_or(Vec::new(), |tags| {
+            Card::join_field(tags)
+                .split_whitespace()
+                .map(str::to_string)
+                .collect()
+        });
+        // One wrong answer per line
+        let choices = choices.map_or(Vec::new(), |choices| {
+            choices
+                .iter()
+                .map(|choice| choice.trim())
+                .filter(|choice| !choice.is_empty())
+                .map(str::to_string)
+                .collect()
+        });
+        let image = image.map_or(String::new(), |image| {
+            Card::join_field(image).trim().to_string()
+        });
+        Ok(Card {
+            front: Card::join_field(front),
+            back: Card::join_field(back),
+            kind,
+            reverse,
+            tags,
+            suspended,
+            choices,
+            image,
+            ..Card::default()
+        })
+    }
+
+    //Trailing empty lines are dropped, editors like to add them
+    fn join_field(lines: Vec<&str>) -> String {
+        lines.join("\n").trim_end_matches('\n').to_string()
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn parse_yes_no(
+    field: Option<Vec<&str>>,
+    name: &str,
+    line: usize,
+) -> Result<Option<bool>, CardParseError> {
+    match field.map(Card::join_field).as_deref().map(str::trim) {
+        Some("yes") => Ok(Some(true)),
+        Some("no") => Ok(Some(false)),
+        Some(value) => Err(CardParseError::InvalidYesNo(
+            line,
+            name.to_string(),
+            value.to_string(),
+        )),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_text_round_trip() {
+        let mut card = Card::new(
+            String::from("What is $\\int_0^1 x\\,dx$ ?"),
+            String::from("$\\frac{1}{2}$\n%% By the power rule\n\\int x\\,dx"),
+        );
+        card.kind = CardKind::TypeAnswer;
+        card.reverse = true;
+        card.tags = vec![String::from("calculus"), String::from("leech")];
+        card.suspended = true;
+        card.choices = vec![String::from("$1$"), String::from("$\\frac{1}{3}$")];
+        card.image = String::from("images/heart.png");
+        assert_eq!(Card::from_editor_text(&card.to_editor_text()), Ok(card));
+    }
+
+    #[test]
+    fn editor_text_errors() {
+        assert_eq!(
+            Card::from_editor_text("%% side\n%% front\na\n%% back\nb\n"),
+            Err(CardParseError::StrayText(1))
+        );
+        assert_eq!(
+            Card::from_editor_text("%% front\na\n"),
+            Err(CardParseError::MissingField(String::from("back")))
+        );
+        assert_eq!(
+            Card::from_editor_text("hello\n%% front\na\n%% back\nb"),
+            Err(CardParseError::StrayText(1))
+        );
+        assert_eq!(
+            Card::from_editor_text("%% front\na\n%% back\nb\n%% front\nc"),
+            Err(CardParseError::DuplicateField(5, String::from("front")))
+        );
+        assert_eq!(
+            Card::from_editor_text("%% front\na\n%% back\nb\n%% kind\nquiz"),
+            Err(CardParseError::UnknownKind(5, String::from("quiz")))
+        );
+        assert_eq!(
+            Card::from_editor_text("%% reverse\nmaybe\n%% front\na\n%% back\nb"),
+            Err(CardParseError::InvalidYesNo(
+                1,
+                String::from("reverse"),
+                String::from("maybe")
+            ))
+        );
+    }
+}
@@ -0,0 +1,138 @@
+// Commands run from the shell instead of the TUI, on the decks of the data
+// directory:
+//   balatui spread <deck> <days>          spread the overdue reviews over the next days
+//   balatui postpone <deck> <days>        push back the reviews due in the next days
+//   balatui advance <deck> <YYYY-MM-DD>   bring forward the reviews due after an exam
+//   balatui simulate <deck> <days> [new cards per day]
+//   balatui simulate-synthetic <cards> <days> [new cards per day]
+//                                         compare the schedulers, see simulator.rs
+
+use chrono::{Local, NaiveDate, TimeZone, Utc};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+use crate::app::App;
+use crate::deck::Deck;
+use crate::reschedule::{self, Reschedule};
+use crate::scheduler::Steps;
+use crate::simulator::{self, Learner};
+use crate::study_queue::DailyLimits;
+
+const USAGE: &str = "usage :
+  balatui                               start the TUI
+  balatui spread <deck> <days>          spread the overdue reviews over the next days
+  balatui postpone <deck> <days>        push back the reviews due in the next days
+  balatui advance <deck> <YYYY-MM-DD>   bring forward the reviews due after an exam
+  balatui simulate <deck> <days> [new cards per day]
+                                        compare the schedulers on the reviews of a deck
+  balatui simulate-synthetic <cards> <days> [new cards per day]
+                                        compare the schedulers on new cards";
+
+pub fn run(args: &[String]) -> Result<()> {
+    if let Some(command @ ("simulate" | "simulate-synthetic")) = args.first().map(String::as_str) {
+        return simulate(command == "simulate-synthetic", &args[1..]);
+    }
+    let [command, deck_name, argument] = args else {
+        usage();
+    };
+    let now = Utc::now();
+    let change = match command.as_str() {
+        "spread" => Reschedule::Spread(parse_days(argument)?),
+        "postpone" => Reschedule::Postpone(parse_days(argument)?),
+        "advance" => {
+            let date = NaiveDate::parse_from_str(argument, "%Y-%m-%d")
+                .wrap_err_with(|| format!("invalid exam date \"{}\"", argument))?;
+            let exam = Local
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+                .earliest()
+                .ok_or_else(|| eyre!("invalid exam date \"{}\"", argument))?;
+            if exam <= now {
+                return Err(eyre!("the exam date {} is not in the future", argument));
+            }
+            Reschedule::Advance(exam.with_timezone(&Utc))
+        }
+        _ => usage(),
+    };
+    let mut deck = load_deck(deck_name)?;
+    let moved = reschedule::reschedule(&mut deck, change, now);
+    deck.save()?;
+    println!("{} items of {} rescheduled", moved, deck.name);
+    Ok(())
+}
+
+//Prints the simulations of the schedulers over the days given
+fn simulate(synthetic: bool, args: &[String]) -> Result<()> {
+    let (source, days, new_cards) = match args {
+        [source, days] => (source, days, None),
+        [source, days, new_cards] => (source, days, Some(new_cards)),
+        _ => usage(),
+    };
+    let days = parse_days(days)?;
+    let (learner, steps, mut daily) = if synthetic {
+        let cards = source
+            .parse()
+            .map_err(|_| eyre!("invalid number of cards \"{}\"", source))?;
+        let learner = Learner::synthetic(cards);
+        println!("{} new cards, default FSRS weights", learner.items());
+        (learner, Steps::default(), DailyLimits::default())
+    } else {
+        let deck = load_deck(source)?;
+        let learner = Learner::from_deck(&deck);
+        match learner.fitted_reviews {
+            Some(reviews) => println!(
+                "{} items of {}, FSRS weights fitted to {} reviews",
+                learner.items(),
+                deck.name,
+                reviews
+            ),
+            None => println!(
+                "{} items of {}, too few reviews to fit the FSRS weights",
+                learner.items(),
+                deck.name
+            ),
+        }
+        (learner, deck.steps, deck.daily)
+    };
+    if let Some(new_cards) = new_cards {
+        daily.new_cards = new_cards
+            .parse()
+            .map_err(|_| eyre!("invalid number of new cards \"{}\"", new_cards))?;
+    }
+    println!(
+        "{} days, {} new cards and {} reviews per day at most\n",
+        days, daily.new_cards, daily.reviews
+    );
+    let now = Utc::now();
+    let simulations: Vec<_> = simulator::configurations(&learner, &steps, &daily)
+        .iter()
+        .map(|configuration| simulator::simulate(&learner, configuration, days, now))
+        .collect();
+    println!("{}", simulator::report(&simulations));
+    Ok(())
+}
+
+//Wrong arguments are not an error worth a report
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2)
+}
+
+fn parse_days(argument: &str) -> Result<u32> {
+    match argument.parse() {
+        Ok(days) if days > 0 => Ok(days),
+        _ => Err(eyre!("invalid number of days \"{}\"", argument)),
+    }
+}
+
+fn load_deck(name: &str) -> Result<Deck> {
+    let decks_path = App::get_cards_path().ok_or_else(|| eyre!("no data directory"))?;
+    let (decks, errors) = Deck::load_all(&decks_path)?;
+    if let Some(deck) = decks.into_iter().find(|deck| deck.name == name) {
+        return Ok(deck);
+    }
+    // The deck may be one of those that could not be loaded
+    let mut message = format!("no deck named \"{}\" in {}", name, decks_path.display());
+    for error in errors {
+        message.push_str(&format!("\n{:#}", error));
+    }
+    Err(eyre!(message))
+}
@@ -0,0 +1,191 @@
+// Cloze deletions, written {{c1::answer}} or {{c1::answer::hint}} in the front
+// of a cloze card. Each cloze number is reviewed separately: the clozes with
+// that number are masked, the others are shown with their answer. The answer
+// can contain LaTeX groups, and a cloze can be written inside math, where the
+// mask stays valid LaTeX.
+
+use std::ops::Range;
+
+const MASK: &str = "[...]";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cloze {
+    pub number: u32,
+    pub range: Range<usize>, // The whole {{...}}
+    pub answer: Range<usize>,
+    pub hint: Option<Range<usize>>,
+    pub in_math: bool,
+}
+
+//Clozes of the text, in order
+pub fn parse(text: &str) -> Vec<Cloze> {
+    let mut clozes = Vec::new();
+    let mut math = MathTracker::default();
+    let mut position = 0;
+    while position < text.len() {
+        if let Some(cloze) = parse_cloze(text, position, math.in_math()) {
+            position = cloze.range.end;
+            clozes.push(cloze);
+            continue;
+        }
+        position += math.advance(&text[position..]);
+    }
+    clozes
+}
+
+//Cloze numbers of the text, sorted and without duplicates
+pub fn numbers(text: &str) -> Vec<u32> {
+    let mut numbers: Vec<u32> = parse(text).iter().map(|cloze| cloze.number).collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+}
+
+//Text with the clozes of the number masked, by their hint if they have one
+pub fn question(text: &str, number: u32) -> String {
+    replace_clozes(text, |cloze| {
+        if cloze.number != number {
+            return text[cloze.answer.clone()].to_string();
+        }
+        let mask = match &cloze.hint {
+            Some(hint) => format!("[{}]", &text[hint.clone()]),
+            None => String::from(MASK),
+        };
+        if cloze.in_math {
+            format!("\\text{{{}}}", mask)
+        } else {
+            mask
+        }
+    })
+}
+
+//Text with every cloze replaced by its answer
+pub fn answer(text: &str) -> String {
+    replace_clozes(text, |cloze| text[cloze.answer.clone()].to_string())
+}
+
+fn replace_clozes(text: &str, replacement: impl Fn(&Cloze) -> String) -> String {
+    let mut result = String::new();
+    let mut position = 0;
+    for cloze in parse(text) {
+        result.push_str(&text[position..cloze.range.start]);
+        result.push_str(&replacement(&cloze));
+        position = cloze.range.end;
+    }
+    result.push_str(&text[position..]);
+    result
+}
+
+//Parses "{{c<number>::answer[::hint]}}" at position. Braces of the answer
+//must be balanced, "\{" and "\}" are not counted.
+fn parse_cloze(text: &str, position: usize, in_math: bool) -> Option<Cloze> {
+    let rest = text[position..].strip_prefix("{{c")?;
+    let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+    let number: u32 = rest[..digits].parse().ok()?;
+    let content_start = position + 3 + digits + "::".len();
+    rest[digits..].strip_prefix("::")?;
+    let mut depth = 0;
+    let mut separator = None;
+    let mut chars = text[content_start..].char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let absolute = content_start + offset;
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '{' => depth += 1,
+            '}' if depth == 0 => {
+                if !text[absolute..].starts_with("}}") {
+                    return None;
+                }
+                let content_end = absolute;
+                let (answer, hint) = match separator {
+                    Some(separator) => (content_start..separator, Some(separator + 2..content_end)),
+                    None => (content_start..content_end, None),
+                };
+                return Some(Cloze {
+                    number,
+                    range: position..content_end + 2,
+                    answer,
+                    hint,
+                    in_math,
+                });
+            }
+            '}' => depth -= 1,
+            ':' if depth == 0 && text[absolute..].starts_with("::") => {
+                separator = Some(absolute);
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Follows the math delimiters $, $$, \( \) and \[ \] while reading a text
+#[derive(Default)]
+struct MathTracker {
+    closing: Option<&'static str>,
+}
+
+impl MathTracker {
+    fn in_math(&self) -> bool {
+        self.closing.is_some()
+    }
+
+    //Reads the start of the text, returns the number of bytes read
+    fn advance(&mut self, text: &str) -> usize {
+        if let Some(closing) = self.closing {
+            if text.starts_with(closing) {
+                self.closing = None;
+                return closing.len();
+            }
+        } else {
+            for (opening, closing) in [("$$", "$$"), ("$", "$"), ("\\(", "\\)"), ("\\[", "\\]")] {
+                if text.starts_with(opening) {
+                    self.closing = Some(closing);
+                    return opening.len();
+                }
+            }
+        }
+        // Escaped characters, "\$" is not a delimiter
+        if let Some(escaped) = text.strip_prefix('\\') {
+            return 1 + escaped.chars().next().map_or(0, char::len_utf8);
+        }
+        text.chars().next().map_or(1, char::len_utf8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masking() {
+        let text = "{{c1::Paris}} is the capital of {{c2::France::country}}";
+        assert_eq!(numbers(text), vec![1, 2]);
+        assert_eq!(question(text, 1), "[...] is the capital of France");
+        assert_eq!(question(text, 2), "Paris is the capital of [country]");
+        assert_eq!(answer(text), "Paris is the capital of France");
+    }
+
+    #[test]
+    fn clozes_in_math() {
+        let text = "$E = {{c1::mc^2}}$ and \\(\\int {{c2::\\frac{1}{x^{2}}}}\\) for \\$ {{c3::x}}";
+        assert_eq!(numbers(text), vec![1, 2, 3]);
+        assert_eq!(
+            question(text, 1),
+            "$E = \\text{[...]}$ and \\(\\int \\frac{1}{x^{2}}\\) for \\$ x"
+        );
+        assert_eq!(
+            question(text, 2),
+            "$E = mc^2$ and \\(\\int \\text{[...]}\\) for \\$ x"
+        );
+        assert_eq!(
+            question(text, 3),
+            "$E = mc^2$ and \\(\\int \\frac{1}{x^{2}}\\) for \\$ [...]"
+        );
+        // Not a cloze: no number, unbalanced braces
+        assert!(parse("{{c::a}} {{c1::a{b}}").is_empty());
+    }
+}
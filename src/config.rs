@@ -0,0 +1,98 @@
+// User configuration, read from <config dir>/balatui/config.toml
+// Every option has a default so the file and each of its sections are optional.
+
+use std::{collections::HashMap, fs, path::PathBuf};
+
+use color_eyre::eyre::{Result, WrapErr};
+use serde::Deserialize;
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub editor: EditorConfig,
+    // [snippets] section, trigger = "body", see snippet.rs for the body syntax
+    pub snippets: HashMap<String, String>,
+    pub spell: SpellConfig,
+}
+
+// [spell] section
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct SpellConfig {
+    //Hunspell dictionary without extension, e.g. "/usr/share/hunspell/en_US"
+    //for en_US.aff and en_US.dic. Spell checking is disabled when not set.
+    pub dictionary: Option<PathBuf>,
+}
+
+// [editor] section
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct EditorConfig {
+    //Minimal number of lines kept visible above and under the cursor
+    pub scrolloff: u16,
+    pub line_numbers: LineNumbers,
+    pub soft_wrap: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LineNumbers {
+    #[default]
+    None,
+    Absolute,
+    //Distance to the cursor line, the cursor line shows its absolute number
+    Relative,
+}
+
+impl Default for EditorConfig {
+    fn default() -> EditorConfig {
+        EditorConfig {
+            scrolloff: 3,
+            line_numbers: LineNumbers::None,
+            soft_wrap: true,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("balatui").join("config.toml"))
+    }
+
+    //A missing file is not an error, the default configuration is used
+    pub fn load() -> Result<Config> {
+        match Config::path() {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(&path)
+                    .wrap_err_with(|| format!("read {}", path.display()))?;
+                Config::parse(&text).wrap_err_with(|| format!("parse {}", path.display()))
+            }
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Config> {
+        Ok(toml::from_str(text)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_section() {
+        let config =
+            Config::parse("[editor]\nscrolloff = 5\nline_numbers = \"relative\"\n").unwrap();
+        assert_eq!(config.editor.scrolloff, 5);
+        assert!(config.editor.line_numbers == LineNumbers::Relative);
+        assert!(config.editor.soft_wrap);
+        assert!(Config::parse("[editor]\nline_numbers = \"roman\"\n").is_err());
+    }
+
+    #[test]
+    fn snippets_section() {
+        let config = Config::parse("[snippets]\nff = '\\frac{$1}{$2}'\n").unwrap();
+        assert_eq!(config.snippets["ff"], "\\frac{$1}{$2}");
+    }
+}
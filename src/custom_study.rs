@@ -0,0 +1,132 @@
+// Custom study sessions, chosen from the deck selection instead of built from
+// the due dates: to cram a topic before an exam or go over the cards forgotten
+// lately. Their grades can be kept out of the schedules (see TestingApp): such
+// cram reviews are not recorded in the review log on purpose, they only count
+// in the summary of the session. Replaying the log, fitting FSRS and the
+// simulator then see the reviews that changed the schedules, and only them.
+
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, Duration, Utc};
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::card::ReviewItem;
+use crate::deck::Deck;
+use crate::scheduler::{Grade, Schedule};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CustomStudy {
+    Tag(String),
+    Forgotten(u32), // Graded Again in the last days
+    Random(usize),  // Number of items
+    AheadOfSchedule,
+}
+
+pub fn custom_queue(deck: &Deck, study: &CustomStudy, now: DateTime<Utc>) -> VecDeque<ReviewItem> {
+    custom_queue_with(deck, study, now, &mut rand::thread_rng())
+}
+
+//Items of the session, the soonest due first except for the random ones
+fn custom_queue_with(
+    deck: &Deck,
+    study: &CustomStudy,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> VecDeque<ReviewItem> {
+    let mut items: Vec<(ReviewItem, Schedule)> = deck
+        .cards
+        .iter()
+        .filter(|card| !card.suspended)
+        .filter(|card| match study {
+            CustomStudy::Tag(tag) => card.has_tag(tag),
+            _ => true,
+        })
+        .flat_map(|card| card.items())
+        .collect();
+    match study {
+        CustomStudy::Forgotten(days) => {
+            let since = now - Duration::days(*days as i64);
+            let forgotten: HashSet<ReviewItem> = deck
+                .review_log
+                .reviews
+                .iter()
+                .filter(|review| review.time >= since && review.grade == Grade::Again)
+                .map(|review| review.item)
+                .collect();
+            items.retain(|(item, _)| forgotten.contains(item));
+        }
+        CustomStudy::Random(count) => {
+            items.shuffle(rng);
+            items.truncate(*count);
+            return items.into_iter().map(|(item, _)| item).collect();
+        }
+        CustomStudy::Tag(_) | CustomStudy::AheadOfSchedule => {}
+    }
+    // The new items last
+    items.sort_by_key(|(_, schedule)| (schedule.is_new(), schedule.due));
+    items.into_iter().map(|(item, _)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::review_log::Review;
+    use crate::scheduler::CardState;
+    use rand::rngs::mock::StepRng;
+    use std::path::Path;
+
+    #[test]
+    fn custom_queues() {
+        let now = DateTime::<Utc>::from_timestamp(1_000 * 86400, 0).unwrap();
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        for id in 1..=4 {
+            let mut card = Card {
+                id,
+                ..Card::default()
+            };
+            if id != 4 {
+                card.set_schedule(
+                    0,
+                    Schedule {
+                        state: CardState::Review,
+                        due: Some(now + Duration::days(10 - id as i64)),
+                        ..Schedule::default()
+                    },
+                );
+            }
+            if id % 2 == 0 {
+                card.add_tag("exam");
+            }
+            deck.cards.push(card);
+        }
+        deck.cards[2].suspended = true;
+        for (card_id, days_ago, grade) in [
+            (1, 2, Grade::Again),
+            (2, 10, Grade::Again),
+            (4, 1, Grade::Good),
+        ] {
+            deck.review_log.reviews.push(Review {
+                item: ReviewItem {
+                    card_id,
+                    ordinal: 0,
+                },
+                time: now - Duration::days(days_ago),
+                grade,
+                time_taken: Duration::zero(),
+                change: None,
+            });
+        }
+        let ids = |study: CustomStudy| -> Vec<u64> {
+            custom_queue_with(&deck, &study, now, &mut StepRng::new(0, 1))
+                .iter()
+                .map(|item| item.card_id)
+                .collect()
+        };
+        assert_eq!(ids(CustomStudy::AheadOfSchedule), vec![2, 1, 4]);
+        assert_eq!(ids(CustomStudy::Tag(String::from("exam"))), vec![2, 4]);
+        assert_eq!(ids(CustomStudy::Forgotten(7)), vec![1]);
+        assert_eq!(ids(CustomStudy::Random(2)).len(), 2);
+    }
+}
@@ -0,0 +1,234 @@
+// A deck is a named list of cards saved in its own TOML file in
+// <data dir>/balatui/decks/, its reviews are logged next to it (review_log.rs)
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Report, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::card::Card;
+use crate::forecast::FuzzSettings;
+use crate::leech::LeechSettings;
+use crate::review_log::{Review, ReviewLog};
+use crate::scheduler::{Schedule, SchedulerSettings, Steps};
+use crate::session::TimeLimits;
+use crate::study_queue::DailyLimits;
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Deck {
+    pub name: String,
+    #[serde(default)]
+    pub scheduler: SchedulerSettings,
+    #[serde(default)]
+    pub steps: Steps,
+    #[serde(default)]
+    pub daily: DailyLimits,
+    #[serde(default)]
+    pub leech: LeechSettings,
+    #[serde(default)]
+    pub time: TimeLimits,
+    #[serde(default)]
+    pub fuzz: FuzzSettings,
+    #[serde(default)]
+    pub cards: Vec<Card>,
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(skip)]
+    pub review_log: ReviewLog,
+}
+
+impl Deck {
+    //The deck is written in <decks_path>/<name>.toml on its first save
+    pub fn new(name: String, decks_path: &Path) -> Deck {
+        let path = decks_path.join(format!("{}.toml", name));
+        let mut review_log = ReviewLog::default();
+        review_log.set_path(path.with_extension("log"));
+        Deck {
+            name,
+            scheduler: SchedulerSettings::default(),
+            steps: Steps::default(),
+            daily: DailyLimits::default(),
+            leech: LeechSettings::default(),
+            time: TimeLimits::default(),
+            fuzz: FuzzSettings::default(),
+            cards: Vec::new(),
+            path,
+            review_log,
+        }
+    }
+
+    pub fn decks_path(data_path: &Path) -> PathBuf {
+        data_path.join("decks")
+    }
+
+    //Every deck of the directory, sorted by name. A deck that can't be loaded
+    //doesn't prevent loading the others, its error is returned with them.
+    pub fn load_all(decks_path: &Path) -> Result<(Vec<Deck>, Vec<Report>)> {
+        if !decks_path.exists() {
+            return Ok((Vec::new(), Vec::new()));
+        }
+        let mut decks = Vec::new();
+        let mut errors = Vec::new();
+        for entry in
+            fs::read_dir(decks_path).wrap_err_with(|| format!("read {}", decks_path.display()))?
+        {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                match Deck::load(&path) {
+                    Ok(deck) => decks.push(deck),
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+        decks.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok((decks, errors))
+    }
+
+    //New deck to add the first cards to, named after no file of the directory
+    //so that saving it overwrites no deck, even one that could not be loaded
+    pub fn placeholder(decks_path: &Path) -> Deck {
+        let name = std::iter::once(String::from("default"))
+            .chain((2..).map(|number| format!("default {}", number)))
+            .find(|name| !decks_path.join(format!("{}.toml", name)).exists())
+            .unwrap();
+        Deck::new(name, decks_path)
+    }
+
+    pub fn load(path: &Path) -> Result<Deck> {
+        let text = fs::read_to_string(path).wrap_err_with(|| format!("read {}", path.display()))?;
+        let mut deck: Deck =
+            toml::from_str(&text).wrap_err_with(|| format!("parse {}", path.display()))?;
+        deck.path = path.to_path_buf();
+        deck.review_log = ReviewLog::load(&path.with_extension("log"))?;
+        Ok(deck)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).wrap_err_with(|| format!("create {}", parent.display()))?;
+        }
+        let text = toml::to_string_pretty(self)?;
+        // Written next to the deck then renamed, a crash can't leave half a deck
+        let temporary_path = self.path.with_extension("toml.tmp");
+        fs::write(&temporary_path, text)
+            .wrap_err_with(|| format!("write {}", temporary_path.display()))?;
+        fs::rename(&temporary_path, &self.path)
+            .wrap_err_with(|| format!("write {}", self.path.display()))
+    }
+
+    pub fn delete_file(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .wrap_err_with(|| format!("delete {}", self.path.display()))?;
+        }
+        self.review_log.delete_file()
+    }
+
+    //The log is written first, a review is never lost even if the deck can't
+    //be saved
+    pub fn log_review(&mut self, review: Review) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).wrap_err_with(|| format!("create {}", parent.display()))?;
+        }
+        self.review_log.append(review)?;
+        self.save()
+    }
+
+    //Forgets the last review and gives its item back the schedule it had before
+    pub fn undo_review(&mut self, previous: Schedule) -> Result<()> {
+        if let Some(review) = self.review_log.remove_last()? {
+            if let Some(card) = self.card_mut(review.item.card_id) {
+                card.set_schedule(review.item.ordinal, previous);
+            }
+        }
+        self.save()
+    }
+
+    //Rebuilds the schedules of the reviewed items by replaying the log with
+    //the scheduler of the deck, returns the number of items rebuilt. The
+    //items never reviewed, or reviewed before the log, keep theirs.
+    pub fn replay_log(&mut self) -> usize {
+        let schedules =
+            self.review_log
+                .replay(self.scheduler.scheduler().as_ref(), &self.steps, &self.fuzz);
+        let mut rebuilt = 0;
+        for (item, schedule) in schedules {
+            if let Some(card) = self.card_mut(item.card_id) {
+                card.set_schedule(item.ordinal, schedule);
+                rebuilt += 1;
+            }
+        }
+        rebuilt
+    }
+
+    //Gives the card an id unused in the deck, returns it
+    pub fn add_card(&mut self, mut card: Card) -> u64 {
+        card.id = self.cards.iter().map(|card| card.id + 1).max().unwrap_or(1);
+        self.cards.push(card);
+        self.cards.last().unwrap().id
+    }
+
+    //Tags of the cards, sorted and without duplicates
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .cards
+            .iter()
+            .flat_map(|card| card.tags.clone())
+            .collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    //Path of the image of an image occlusion card, a relative one is in the
+    //directory of the deck file
+    pub fn image_path(&self, card: &Card) -> PathBuf {
+        match self.path.parent() {
+            Some(directory) => directory.join(&card.image),
+            None => PathBuf::from(&card.image),
+        }
+    }
+
+    pub fn card(&self, id: u64) -> Option<&Card> {
+        self.cards.iter().find(|card| card.id == id)
+    }
+
+    pub fn card_mut(&mut self, id: u64) -> Option<&mut Card> {
+        self.cards.iter_mut().find(|card| card.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let decks_path = std::env::temp_dir().join(format!("balatui-decks-{}", std::process::id()));
+        let mut deck = Deck::new(String::from("maths"), &decks_path);
+        assert_eq!(
+            deck.add_card(Card::new(String::from("$1+1$"), String::from("2"))),
+            1
+        );
+        assert_eq!(deck.add_card(Card::default()), 2);
+        deck.cards.remove(0);
+        assert_eq!(deck.add_card(Card::default()), 3);
+        deck.scheduler = SchedulerSettings::Fsrs(Default::default());
+        deck.save().unwrap();
+        // A broken deck is reported, the others are still loaded
+        fs::write(decks_path.join("default.toml"), "cards = 3").unwrap();
+        let (decks, errors) = Deck::load_all(&decks_path).unwrap();
+        let placeholder = Deck::placeholder(&decks_path);
+        fs::remove_dir_all(&decks_path).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(placeholder.name, "default 2");
+        assert_eq!(decks.len(), 1);
+        assert_eq!(decks[0].name, "maths");
+        assert_eq!(decks[0].cards, deck.cards);
+        assert_eq!(decks[0].scheduler, deck.scheduler);
+    }
+}
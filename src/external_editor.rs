@@ -0,0 +1,57 @@
+// Editing of text in the user's own editor ($VISUAL, then $EDITOR).
+// The terminal has to be given back to the editor before calling edit_text,
+// see App::open_external_editor.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use color_eyre::eyre::{bail, Result, WrapErr};
+
+const FALLBACK_EDITOR: &str = "vi";
+
+fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|variable| env::var(variable).ok())
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or(String::from(FALLBACK_EDITOR))
+}
+
+fn temporary_file_path() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+    // .tex so that editors pick LaTeX highlighting
+    env::temp_dir().join(format!("balatui-{}-{}.tex", std::process::id(), nanos))
+}
+
+//Writes the text in a temporary file, opens it in the editor and returns the
+//content of the file once the editor exits
+pub fn edit_text(text: &str) -> Result<String> {
+    let path = temporary_file_path();
+    fs::write(&path, text).wrap_err("write temporary file")?;
+    let result = run_editor(&path);
+    let _ = fs::remove_file(&path);
+    result
+}
+
+fn run_editor(path: &Path) -> Result<String> {
+    let command = editor_command();
+    // The variable may contain arguments, e.g. "code --wait"
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or(FALLBACK_EDITOR);
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .wrap_err_with(|| format!("launch editor \"{}\"", command))?;
+    if !status.success() {
+        bail!("editor \"{}\" exited with {}", command, status);
+    }
+    fs::read_to_string(path).wrap_err("read temporary file")
+}
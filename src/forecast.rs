@@ -0,0 +1,258 @@
+// Reviews due in the coming days, and the spreading of the new due dates: the
+// interval given by the scheduler is fuzzed by a few days, towards the days
+// with the fewest reviews already due. Cards learned together then stop coming
+// back together, e.g. after a large import. A replay of the review log keeps
+// the fuzzed intervals logged, see fuzz_replayed.
+
+use chrono::{DateTime, Local, TimeZone, Utc};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::card::ReviewItem;
+use crate::deck::Deck;
+use crate::review_log::Review;
+use crate::scheduler::{days, CardState, Schedule};
+use crate::study_queue::day_start;
+
+// Intervals shorter than this, in days, are not fuzzed
+const MIN_FUZZED_INTERVAL: f64 = 2.5;
+// Share of the interval added to the fuzz range for the days in each range,
+// the longer intervals are fuzzed less in proportion
+const FUZZ_RANGES: [(f64, f64, f64); 3] =
+    [(2.5, 7.0, 0.15), (7.0, 20.0, 0.1), (20.0, f64::MAX, 0.05)];
+
+// [fuzz] table of the deck file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FuzzSettings {
+    pub enabled: bool,
+    // The fuzzed interval favors the days with fewer reviews
+    pub load_balance: bool,
+}
+
+impl Default for FuzzSettings {
+    fn default() -> FuzzSettings {
+        FuzzSettings {
+            enabled: true,
+            load_balance: true,
+        }
+    }
+}
+
+//Reviews due on each of the next days, today first with the overdue ones.
+//The days start at the rollover hour of the deck.
+pub fn forecast(deck: &Deck, now: DateTime<Utc>, days: usize) -> Vec<usize> {
+    forecast_in(deck, now, days, &Local, None)
+}
+
+//The item left out is the one being fuzzed, its old due date is no load
+fn forecast_in(
+    deck: &Deck,
+    now: DateTime<Utc>,
+    days: usize,
+    timezone: &impl TimeZone,
+    left_out: Option<ReviewItem>,
+) -> Vec<usize> {
+    let start = day_start(now, deck.daily.rollover_hour, timezone);
+    let mut counts = vec![0; days];
+    let dues = deck
+        .cards
+        .iter()
+        .filter(|card| !card.suspended)
+        .flat_map(|card| card.items())
+        .filter(|(item, _)| Some(*item) != left_out)
+        .filter_map(|(_, schedule)| schedule.due.filter(|_| !schedule.is_new()));
+    for due in dues {
+        let day = (due - start).num_days().max(0) as usize;
+        if let Some(count) = counts.get_mut(day) {
+            *count += 1;
+        }
+    }
+    counts
+}
+
+//Days the interval can be moved to, at least 2 days
+pub fn fuzz_range(interval: f64) -> (u32, u32) {
+    if interval < MIN_FUZZED_INTERVAL {
+        let days = interval.round() as u32;
+        return (days, days);
+    }
+    let delta: f64 = 1.0
+        + FUZZ_RANGES
+            .iter()
+            .map(|(start, end, factor)| factor * (interval.min(*end) - start).max(0.0))
+            .sum::<f64>();
+    let low = ((interval - delta).round() as u32).max(2);
+    let high = ((interval + delta).round() as u32).max(low);
+    (low, high)
+}
+
+//Fuzzes the interval of a schedule just given by a review, only for the
+//items in review
+pub fn fuzz(deck: &Deck, item: ReviewItem, schedule: &mut Schedule, now: DateTime<Utc>) {
+    fuzz_with(deck, item, schedule, now, &mut rand::thread_rng());
+}
+
+fn fuzz_with(
+    deck: &Deck,
+    item: ReviewItem,
+    schedule: &mut Schedule,
+    now: DateTime<Utc>,
+    rng: &mut impl Rng,
+) {
+    if !is_fuzzed(&deck.fuzz, schedule) {
+        return;
+    }
+    let (low, high) = fuzz_range(schedule.interval);
+    let interval = if deck.fuzz.load_balance {
+        let counts = forecast_in(deck, now, high as usize + 1, &Local, Some(item));
+        // The fewer reviews on a day, the likelier it is chosen
+        let weights = (low..=high).map(|day| 1.0 / ((counts[day as usize] + 1) as f64).powi(2));
+        let index = WeightedIndex::new(weights).unwrap();
+        low + index.sample(rng) as u32
+    } else {
+        rng.gen_range(low..=high)
+    };
+    set_interval(schedule, interval as f64, now);
+}
+
+//Fuzzes the interval of a schedule given by a replayed review like the review
+//did: its logged interval is kept when in the range of the scheduler, which
+//it is unless the scheduler changed. Otherwise the interval is drawn from the
+//range with the item and time of the review as seed, without load balancing
+//as the reviews due at that time are not known.
+pub fn fuzz_replayed(settings: &FuzzSettings, schedule: &mut Schedule, review: &Review) {
+    if !is_fuzzed(settings, schedule) {
+        return;
+    }
+    let (low, high) = fuzz_range(schedule.interval);
+    let logged = review
+        .change
+        .as_ref()
+        .map(|change| change.interval)
+        .filter(|interval| (low as f64..=high as f64).contains(interval));
+    let interval = logged.unwrap_or_else(|| {
+        let seed = review.item.card_id
+            ^ (review.item.ordinal as u64) << 32
+            ^ review.time.timestamp_millis() as u64;
+        StdRng::seed_from_u64(seed).gen_range(low..=high) as f64
+    });
+    set_interval(schedule, interval, review.time);
+}
+
+fn is_fuzzed(settings: &FuzzSettings, schedule: &Schedule) -> bool {
+    settings.enabled
+        && schedule.state == CardState::Review
+        && schedule.interval >= MIN_FUZZED_INTERVAL
+}
+
+fn set_interval(schedule: &mut Schedule, interval: f64, now: DateTime<Utc>) {
+    schedule.interval = interval;
+    schedule.due = now.checked_add_signed(days(interval));
+}
+
+//Line of a bar chart of the forecast
+pub fn forecast_line(day: usize, count: usize, largest: usize, width: usize) -> String {
+    let name = match day {
+        0 => String::from("Today"),
+        1 => String::from("Tomorrow"),
+        _ => format!("In {} days", day),
+    };
+    let bar = "#".repeat((count * width).div_ceil(largest.max(1)));
+    format!("{:<11}{:>5} {}", name, count, bar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::review_log::ScheduleChange;
+    use crate::scheduler::Grade;
+    use chrono::{Duration, FixedOffset};
+    use rand::rngs::mock::StepRng;
+    use std::path::Path;
+
+    #[test]
+    fn forecast_and_load_balance() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let now = DateTime::<Utc>::from_timestamp(1_000 * 86400 + 12 * 3600, 0).unwrap();
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        deck.daily.rollover_hour = 0;
+        // 2 overdue, then 10 reviews due in 5 days and 1 in 6 days
+        for (days_away, count) in [(-3, 2), (5, 10), (6, 1)] {
+            for _ in 0..count {
+                let mut card = Card::default();
+                card.set_schedule(
+                    0,
+                    Schedule {
+                        state: CardState::Review,
+                        due: Some(now + Duration::days(days_away)),
+                        ..Schedule::default()
+                    },
+                );
+                deck.add_card(card);
+            }
+        }
+        deck.add_card(Card::default());
+        assert_eq!(
+            forecast_in(&deck, now, 7, &utc, None),
+            vec![2, 0, 0, 0, 0, 10, 1]
+        );
+        let first = ReviewItem {
+            card_id: 1,
+            ordinal: 0,
+        };
+        assert_eq!(forecast_in(&deck, now, 1, &utc, Some(first)), vec![1]);
+
+        assert_eq!(fuzz_range(1.0), (1, 1));
+        assert_eq!(fuzz_range(5.0), (4, 6));
+        assert_eq!(fuzz_range(100.0), (93, 107));
+        let mut schedule = Schedule {
+            state: CardState::Review,
+            interval: 5.0,
+            ..Schedule::default()
+        };
+        // Day 4 is empty, the busy day 5 is the least likely
+        let mut chosen = [0; 3];
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..300 {
+            let mut fuzzed = schedule.clone();
+            fuzz_with(&deck, first, &mut fuzzed, now, &mut rng);
+            chosen[fuzzed.interval as usize - 4] += 1;
+        }
+        assert!(chosen[1] < chosen[0] && chosen[1] < chosen[2]);
+
+        // Replayed, the logged interval is kept if the scheduler could give it
+        let review = |interval| Review {
+            item: ReviewItem {
+                card_id: 1,
+                ordinal: 0,
+            },
+            time: now,
+            grade: Grade::Good,
+            time_taken: Duration::zero(),
+            change: Some(ScheduleChange {
+                interval,
+                ..ScheduleChange::new(&schedule, &schedule)
+            }),
+        };
+        let replayed = |review: &Review| {
+            let mut replayed = schedule.clone();
+            fuzz_replayed(&deck.fuzz, &mut replayed, review);
+            replayed
+        };
+        assert_eq!(replayed(&review(6.0)).interval, 6.0);
+        assert_eq!(replayed(&review(6.0)).due, Some(now + Duration::days(6)));
+        let drawn = replayed(&review(9.0)).interval;
+        assert!((4.0..=6.0).contains(&drawn));
+        assert_eq!(replayed(&review(9.0)).interval, drawn);
+        let mut unfuzzed = schedule.clone();
+        deck.fuzz.enabled = false;
+        fuzz_replayed(&deck.fuzz, &mut unfuzzed, &review(6.0));
+        assert_eq!(unfuzzed.interval, 5.0);
+        fuzz_with(&deck, first, &mut schedule, now, &mut StepRng::new(0, 1));
+        assert_eq!(schedule.interval, 5.0);
+    }
+}
@@ -0,0 +1,516 @@
+// Answers that are formulas are checked for mathematical equivalence instead
+// of character by character: both are parsed, from LaTeX or plain text, then
+// evaluated at random values of their variables. "2x+x" is then right for
+// "3x", as is "1/2" for "$\frac{1}{2}$".
+
+use std::collections::{BTreeSet, HashMap};
+use std::f64::consts::{E, PI};
+
+use rand::Rng;
+
+// Values of the variables are drawn in this range, positive so that roots
+// and logarithms are defined
+const SAMPLE_RANGE: (f64, f64) = (0.5, 3.0);
+const SAMPLES: usize = 12;
+// Samples where both sides are defined, needed to tell them equivalent
+const MIN_DEFINED_SAMPLES: usize = 4;
+const TOLERANCE: f64 = 1e-6; // Relative
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Variable(String),
+    Negation(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+    Function(Function, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Arcsin,
+    Arccos,
+    Arctan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Ln,
+    Log, // In base 10
+    Exp,
+    Sqrt,
+    Abs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Variable(String),
+    Function(Function),
+    Plus,
+    Minus,
+    Times,
+    Divide,
+    Power,
+    Open(char),
+    Close,
+    Frac,
+    Sqrt,
+}
+
+// Words read as functions or constants, the longest first as "sin" starts "sinh"
+const NAMES: [(&str, Option<Function>); 16] = [
+    ("arcsin", Some(Function::Arcsin)),
+    ("arccos", Some(Function::Arccos)),
+    ("arctan", Some(Function::Arctan)),
+    ("sqrt", Some(Function::Sqrt)),
+    ("sinh", Some(Function::Sinh)),
+    ("cosh", Some(Function::Cosh)),
+    ("tanh", Some(Function::Tanh)),
+    ("sin", Some(Function::Sin)),
+    ("cos", Some(Function::Cos)),
+    ("tan", Some(Function::Tan)),
+    ("exp", Some(Function::Exp)),
+    ("log", Some(Function::Log)),
+    ("abs", Some(Function::Abs)),
+    ("ln", Some(Function::Ln)),
+    ("pi", None),
+    ("e", None),
+];
+
+impl Function {
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Function::Sin => x.sin(),
+            Function::Cos => x.cos(),
+            Function::Tan => x.tan(),
+            Function::Arcsin => x.asin(),
+            Function::Arccos => x.acos(),
+            Function::Arctan => x.atan(),
+            Function::Sinh => x.sinh(),
+            Function::Cosh => x.cosh(),
+            Function::Tanh => x.tanh(),
+            Function::Ln => x.ln(),
+            Function::Log => x.log10(),
+            Function::Exp => x.exp(),
+            Function::Sqrt => x.sqrt(),
+            Function::Abs => x.abs(),
+        }
+    }
+}
+
+impl Expr {
+    //NaN where the expression is undefined, or a variable has no value
+    pub fn evaluate(&self, values: &HashMap<String, f64>) -> f64 {
+        match self {
+            Expr::Number(number) => *number,
+            Expr::Variable(name) => values.get(name).copied().unwrap_or(f64::NAN),
+            Expr::Negation(expr) => -expr.evaluate(values),
+            Expr::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                    Operator::Power => left.powf(right),
+                }
+            }
+            Expr::Function(function, argument) => function.apply(argument.evaluate(values)),
+        }
+    }
+
+    pub fn variables(&self, variables: &mut BTreeSet<String>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Variable(name) => {
+                variables.insert(name.clone());
+            }
+            Expr::Negation(expr) | Expr::Function(_, expr) => expr.variables(variables),
+            Expr::Binary(_, left, right) => {
+                left.variables(variables);
+                right.variables(variables);
+            }
+        }
+    }
+}
+
+//Reads "$\frac{x^2}{2}$", "x^2/2" or "(1/2) x**2" alike, None for a text that
+//is not a formula
+pub fn parse(text: &str) -> Option<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(text)?,
+        position: 0,
+    };
+    let expr = parser.sum()?;
+    (parser.position == parser.tokens.len()).then_some(expr)
+}
+
+//Whether the expected answer is checked as a formula: written in math mode,
+//or made of numbers, operators and single letter variables. "Paris" is not.
+pub fn is_formula(text: &str) -> bool {
+    let text = text.trim();
+    let in_math = text.len() > 1 && text.starts_with('$') && text.ends_with('$')
+        || text.starts_with("\\(") && text.ends_with("\\)");
+    // Outside of the LaTeX commands
+    let words_are_names = text
+        .split('\\')
+        .enumerate()
+        .map(|(index, part)| match index {
+            0 => part,
+            _ => part.trim_start_matches(|c: char| c.is_ascii_alphabetic()),
+        })
+        .flat_map(|part| part.split(|c: char| !c.is_ascii_alphabetic()))
+        .all(|word| word.len() < 2 || NAMES.iter().any(|(name, _)| *name == word));
+    let has_number_or_operator = text
+        .chars()
+        .any(|c| c.is_ascii_digit() || "+-*/^".contains(c));
+    parse(text).is_some() && (in_math || words_are_names && has_number_or_operator)
+}
+
+pub fn equivalent(typed: &str, expected: &str) -> bool {
+    equivalent_with(typed, expected, &mut rand::thread_rng())
+}
+
+//Both sides agree within the tolerance wherever they are both defined, at
+//enough random values of their variables
+fn equivalent_with(typed: &str, expected: &str, rng: &mut impl Rng) -> bool {
+    let (Some(typed), Some(expected)) = (parse(typed), parse(expected)) else {
+        return false;
+    };
+    let mut variables = BTreeSet::new();
+    typed.variables(&mut variables);
+    expected.variables(&mut variables);
+    let mut defined = 0;
+    for _ in 0..SAMPLES {
+        let values: HashMap<String, f64> = variables
+            .iter()
+            .map(|name| (name.clone(), rng.gen_range(SAMPLE_RANGE.0..SAMPLE_RANGE.1)))
+            .collect();
+        let (a, b) = (typed.evaluate(&values), expected.evaluate(&values));
+        if !a.is_finite() || !b.is_finite() {
+            continue;
+        }
+        if (a - b).abs() > TOLERANCE * a.abs().max(b.abs()).max(1.0) {
+            return false;
+        }
+        defined += 1;
+    }
+    defined >= MIN_DEFINED_SAMPLES
+}
+
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            _ if c.is_whitespace() => {}
+            '$' => {}
+            '0'..='9' | '.' => {
+                let start = i - 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                tokens.push(Token::Number(number.parse().ok()?));
+            }
+            'a'..='z' | 'A'..='Z' => {
+                let start = i - 1;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.extend(word_tokens(&word));
+                // A subscript is part of the name of the last variable, x_1 or x_{ij}
+                if let (Some(Token::Variable(name)), Some('_')) = (tokens.last_mut(), chars.get(i))
+                {
+                    let (subscript, end) = subscript(&chars, i + 1)?;
+                    name.push('_');
+                    name.push_str(&subscript);
+                    i = end;
+                }
+            }
+            '\\' => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                if i == start {
+                    // "\," and the like only add space
+                    let symbol = chars.get(i)?;
+                    i += 1;
+                    match symbol {
+                        ',' | ';' | ':' | '!' | ' ' => {}
+                        '{' | '(' => tokens.push(Token::Open('(')),
+                        '}' | ')' => tokens.push(Token::Close),
+                        _ => return None,
+                    }
+                    continue;
+                }
+                let command: String = chars[start..i].iter().collect();
+                tokens.extend(command_token(&command));
+            }
+            '+' => tokens.push(Token::Plus),
+            '-' | '−' => tokens.push(Token::Minus),
+            '*' if chars.get(i) == Some(&'*') => {
+                i += 1;
+                tokens.push(Token::Power);
+            }
+            '*' | '·' | '×' => tokens.push(Token::Times),
+            '/' | '÷' => tokens.push(Token::Divide),
+            '^' => tokens.push(Token::Power),
+            '(' | '[' | '{' => tokens.push(Token::Open(c)),
+            ')' | ']' | '}' => tokens.push(Token::Close),
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+//A run of letters: known names, each other letter a variable
+fn word_tokens(word: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut rest = word;
+    while !rest.is_empty() {
+        if let Some((name, function)) = NAMES.iter().find(|(name, _)| rest.starts_with(name)) {
+            tokens.push(match (function, *name) {
+                (Some(function), _) => Token::Function(*function),
+                (None, "pi") => Token::Number(PI),
+                (None, _) => Token::Number(E),
+            });
+            rest = &rest[name.len()..];
+        } else {
+            let letter = &rest[..1];
+            tokens.push(Token::Variable(letter.to_string()));
+            rest = &rest[1..];
+        }
+    }
+    tokens
+}
+
+//Tokens of a LaTeX command, the unknown ones like \alpha are variables
+fn command_token(command: &str) -> Option<Token> {
+    match command {
+        "frac" | "dfrac" | "tfrac" => Some(Token::Frac),
+        "sqrt" => Some(Token::Sqrt),
+        "cdot" | "times" | "ast" => Some(Token::Times),
+        "div" => Some(Token::Divide),
+        "pi" => Some(Token::Number(PI)),
+        "left" | "right" | "big" | "Big" | "bigl" | "bigr" | "Bigl" | "Bigr" | "quad" | "qquad"
+        | "mathrm" | "operatorname" => None,
+        _ => match NAMES.iter().find(|(name, _)| *name == command) {
+            Some((_, Some(function))) => Some(Token::Function(*function)),
+            _ => Some(Token::Variable(format!("\\{}", command))),
+        },
+    }
+}
+
+//Text of a subscript starting at start, and the index after it
+fn subscript(chars: &[char], start: usize) -> Option<(String, usize)> {
+    match chars.get(start)? {
+        '{' => {
+            let end = start + chars[start..].iter().position(|c| *c == '}')?;
+            Some((chars[start + 1..end].iter().collect(), end + 1))
+        }
+        c if c.is_ascii_alphanumeric() => Some((c.to_string(), start + 1)),
+        _ => None,
+    }
+}
+
+// Recursive descent, from the lowest precedence: sums, products (also written
+// without operator, "2x"), negations, powers and atoms
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Option<Expr> {
+        let mut expr = self.product()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => Operator::Add,
+                Some(Token::Minus) => Operator::Subtract,
+                _ => return Some(expr),
+            };
+            self.position += 1;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Option<Expr> {
+        let mut expr = self.unary()?;
+        loop {
+            let (operator, right) = match self.peek() {
+                Some(Token::Times) => {
+                    self.position += 1;
+                    (Operator::Multiply, self.unary()?)
+                }
+                Some(Token::Divide) => {
+                    self.position += 1;
+                    (Operator::Divide, self.unary()?)
+                }
+                // Implicit, a minus sign after a factor is a subtraction
+                Some(
+                    Token::Number(_)
+                    | Token::Variable(_)
+                    | Token::Function(_)
+                    | Token::Open(_)
+                    | Token::Frac
+                    | Token::Sqrt,
+                ) => (Operator::Multiply, self.power()?),
+                _ => return Some(expr),
+            };
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        if self.eat(&Token::Minus) {
+            return Some(Expr::Negation(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::Plus) {
+            return self.unary();
+        }
+        self.power()
+    }
+
+    //Right associative, "-x^2" is "-(x^2)" and "2^-1" a half
+    fn power(&mut self) -> Option<Expr> {
+        let base = self.atom()?;
+        if self.eat(&Token::Power) {
+            let exponent = self.unary()?;
+            return Some(Expr::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        Some(base)
+    }
+
+    fn atom(&mut self) -> Option<Expr> {
+        match self.next()? {
+            Token::Number(number) => Some(Expr::Number(number)),
+            Token::Variable(name) => Some(Expr::Variable(name)),
+            Token::Open(_) => {
+                let expr = self.sum()?;
+                self.eat(&Token::Close).then_some(expr)
+            }
+            // "\sin^2 x" is the square of the sine, the argument without
+            // parentheses is one factor: "\sin 2x" is "\sin(2) x"
+            Token::Function(function) => {
+                let exponent = if self.eat(&Token::Power) {
+                    Some(self.atom()?)
+                } else {
+                    None
+                };
+                let expr = Expr::Function(function, Box::new(self.power()?));
+                Some(match exponent {
+                    Some(exponent) => {
+                        Expr::Binary(Operator::Power, Box::new(expr), Box::new(exponent))
+                    }
+                    None => expr,
+                })
+            }
+            Token::Frac => {
+                let numerator = self.atom()?;
+                let denominator = self.atom()?;
+                Some(Expr::Binary(
+                    Operator::Divide,
+                    Box::new(numerator),
+                    Box::new(denominator),
+                ))
+            }
+            // \sqrt[n]{x}, the index is optional
+            Token::Sqrt => {
+                if self.eat(&Token::Open('[')) {
+                    let index = self.sum()?;
+                    if !self.eat(&Token::Close) {
+                        return None;
+                    }
+                    let radicand = self.atom()?;
+                    let exponent = Expr::Binary(
+                        Operator::Divide,
+                        Box::new(Expr::Number(1.0)),
+                        Box::new(index),
+                    );
+                    return Some(Expr::Binary(
+                        Operator::Power,
+                        Box::new(radicand),
+                        Box::new(exponent),
+                    ));
+                }
+                Some(Expr::Function(Function::Sqrt, Box::new(self.atom()?)))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn equivalent_formulas() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut equivalent = |typed, expected| equivalent_with(typed, expected, &mut rng);
+        for (typed, expected) in [
+            ("2x+x", "3x"),
+            ("1/2", "$\\frac{1}{2}$"),
+            ("x+1", "\\frac{x^2-1}{x-1}"),
+            ("(a+b)**2", "a^2 + 2ab + b^2"),
+            ("1", "\\sin^2 x + \\cos^2 x"),
+            ("2", "\\sqrt[3]{8}"),
+            ("x_1 \\cdot 2", "2x_{1}"),
+            ("e^{\\ln(2y)}", "2 \\, y"),
+            ("-x^2", "0 - x \\cdot x"),
+        ] {
+            assert!(equivalent(typed, expected), "{} == {}", typed, expected);
+        }
+        for (typed, expected) in [("2x", "3x"), ("x^2", "2x"), ("y", "x"), ("(", "1")] {
+            assert!(!equivalent(typed, expected), "{} != {}", typed, expected);
+        }
+        assert!(is_formula("3x"));
+        assert!(is_formula("$\\alpha + \\beta$"));
+        assert!(is_formula("\\sin(x) + 1"));
+        assert!(is_formula("\\frac{\\pi}{2}"));
+        assert!(!is_formula("Paris"));
+        assert!(!is_formula("x = 2"));
+        assert!(!is_formula("$\\frac{1}{2}$\n\nBy the power rule"));
+    }
+}
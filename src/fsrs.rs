@@ -0,0 +1,336 @@
+// FSRS (Free Spaced Repetition Scheduler), version 4.5. The memory of a card
+// is described by:
+//   stability       days after which the recall probability falls to 90%
+//   difficulty      from 1 to 10, how hard the stability is to increase
+//   retrievability  probability of recalling the card now
+// The 17 weights of the model can be fitted to the reviews of the user.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::card::ReviewItem;
+use crate::review_log::Review;
+use crate::scheduler::{days, Grade, Schedule, Scheduler, MAX_INTERVAL};
+
+const DECAY: f64 = -0.5;
+// Chosen so that the retrievability is 90% when the elapsed time equals the stability
+const FACTOR: f64 = 19.0 / 81.0;
+
+pub const DEFAULT_WEIGHTS: [f64; 17] = [
+    0.4872, 1.4003, 3.7145, 13.8206, 5.1618, 1.2298, 0.8975, 0.031, 1.6474, 0.1367, 1.0461, 2.1072,
+    0.0793, 0.3246, 1.587, 0.2272, 2.8755,
+];
+
+// Range of each weight allowed by the optimizer
+const WEIGHT_BOUNDS: [(f64, f64); 17] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.5),
+    (0.0, 3.0),
+    (0.1, 0.8),
+    (0.01, 2.5),
+    (0.5, 5.0),
+    (0.01, 0.2),
+    (0.01, 0.9),
+    (0.01, 2.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+];
+
+// Reviews needed before the optimizer is trusted, fewer would overfit
+const MINIMAL_PREDICTIONS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FsrsParameters {
+    pub weights: [f64; 17],
+    //Recall probability wanted when a card comes back, higher means more reviews
+    pub desired_retention: f64,
+}
+
+pub struct Fsrs {
+    parameters: FsrsParameters,
+}
+
+pub struct Optimization {
+    pub weights: [f64; 17],
+    pub loss_before: f64,
+    pub loss_after: f64,
+    pub predictions: usize,
+}
+
+impl Default for FsrsParameters {
+    fn default() -> FsrsParameters {
+        FsrsParameters {
+            weights: DEFAULT_WEIGHTS,
+            desired_retention: 0.9,
+        }
+    }
+}
+
+impl Fsrs {
+    pub fn new(parameters: FsrsParameters) -> Fsrs {
+        Fsrs { parameters }
+    }
+
+    //Days until the recall probability falls to the desired retention
+    pub fn interval(&self, stability: f64) -> f64 {
+        let retention = self.parameters.desired_retention;
+        (stability / FACTOR * (retention.powf(1.0 / DECAY) - 1.0))
+            .round()
+            .clamp(1.0, MAX_INTERVAL)
+    }
+
+    //Stability and difficulty of a reviewed card. Cards reviewed before the
+    //deck used FSRS only have an interval, it is taken as their stability.
+    fn memory_state(&self, schedule: &Schedule) -> Option<(f64, f64)> {
+        if schedule.stability > 0.0 {
+            Some((schedule.stability, schedule.difficulty))
+        } else if !schedule.is_new() {
+            let weights = &self.parameters.weights;
+            Some((
+                schedule.interval.max(0.1),
+                initial_difficulty(weights, Grade::Good),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+impl Scheduler for Fsrs {
+    fn next(&self, schedule: &Schedule, grade: Grade, now: DateTime<Utc>) -> Schedule {
+        let weights = &self.parameters.weights;
+        let (stability, difficulty) = match self.memory_state(schedule) {
+            None => initial_state(weights, grade),
+            Some((stability, difficulty)) => {
+                let last_review = schedule
+                    .last_review
+                    .or(schedule.due.map(|due| due - days(schedule.interval)))
+                    .unwrap_or(now);
+                let elapsed = elapsed_days(last_review, now);
+                let retrievability = retrievability(elapsed, stability);
+                next_state(weights, stability, difficulty, retrievability, grade)
+            }
+        };
+        let mut next = schedule.clone();
+        if grade == Grade::Again {
+            if schedule.repetitions > 0 {
+                next.lapses += 1;
+            }
+            next.repetitions = 0;
+        } else {
+            next.repetitions += 1;
+        }
+        next.stability = stability;
+        next.difficulty = difficulty;
+        next.interval = self.interval(stability);
+        next.due = now.checked_add_signed(days(next.interval));
+        next.last_review = Some(now);
+        next
+    }
+}
+
+pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+    (1.0 + FACTOR * elapsed_days / stability).powf(DECAY)
+}
+
+fn elapsed_days(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    ((to - from).num_seconds() as f64 / 86400.0).max(0.0)
+}
+
+fn grade_value(grade: Grade) -> f64 {
+    grade.number() as f64
+}
+
+fn initial_difficulty(weights: &[f64; 17], grade: Grade) -> f64 {
+    (weights[4] - (grade_value(grade) - 3.0) * weights[5]).clamp(1.0, 10.0)
+}
+
+pub fn initial_state(weights: &[f64; 17], grade: Grade) -> (f64, f64) {
+    (weights[grade as usize], initial_difficulty(weights, grade))
+}
+
+pub fn next_state(
+    weights: &[f64; 17],
+    stability: f64,
+    difficulty: f64,
+    retrievability: f64,
+    grade: Grade,
+) -> (f64, f64) {
+    let w = weights;
+    let changed_difficulty = difficulty - w[6] * (grade_value(grade) - 3.0);
+    // Mean reversion towards the difficulty of a card first graded Good
+    let next_difficulty = (w[7] * initial_difficulty(w, Grade::Good)
+        + (1.0 - w[7]) * changed_difficulty)
+        .clamp(1.0, 10.0);
+    let next_stability = if grade == Grade::Again {
+        (w[11]
+            * difficulty.powf(-w[12])
+            * ((stability + 1.0).powf(w[13]) - 1.0)
+            * (w[14] * (1.0 - retrievability)).exp())
+        .min(stability)
+    } else {
+        let hard_penalty = if grade == Grade::Hard { w[15] } else { 1.0 };
+        let easy_bonus = if grade == Grade::Easy { w[16] } else { 1.0 };
+        stability
+            * (w[8].exp()
+                * (11.0 - difficulty)
+                * stability.powf(-w[9])
+                * ((w[10] * (1.0 - retrievability)).exp() - 1.0)
+                * hard_penalty
+                * easy_bonus
+                + 1.0)
+    };
+    (next_stability.max(0.01), next_difficulty)
+}
+
+//Reviews of each item, in chronological order
+fn item_histories(reviews: &[Review]) -> Vec<Vec<(DateTime<Utc>, Grade)>> {
+    let mut histories: BTreeMap<ReviewItem, Vec<(DateTime<Utc>, Grade)>> = BTreeMap::new();
+    for review in reviews {
+        histories
+            .entry(review.item)
+            .or_default()
+            .push((review.time, review.grade));
+    }
+    let mut histories: Vec<_> = histories.into_values().collect();
+    for history in histories.iter_mut() {
+        history.sort_by_key(|(time, _)| *time);
+    }
+    histories
+}
+
+//Mean log loss of the recall probabilities predicted before each review, and
+//the number of predictions
+fn loss(weights: &[f64; 17], histories: &[Vec<(DateTime<Utc>, Grade)>]) -> (f64, usize) {
+    let mut total = 0.0;
+    let mut predictions = 0;
+    for history in histories {
+        let Some(((mut last_time, first_grade), rest)) = history.split_first() else {
+            continue;
+        };
+        let (mut stability, mut difficulty) = initial_state(weights, *first_grade);
+        for (time, grade) in rest {
+            let probability =
+                retrievability(elapsed_days(last_time, *time), stability).clamp(1e-6, 1.0 - 1e-6);
+            total -= if *grade == Grade::Again {
+                (1.0 - probability).ln()
+            } else {
+                probability.ln()
+            };
+            predictions += 1;
+            (stability, difficulty) =
+                next_state(weights, stability, difficulty, probability, *grade);
+            last_time = *time;
+        }
+    }
+    (total / predictions.max(1) as f64, predictions)
+}
+
+//Fits the weights to the review log with gradient descent (Adam, gradients
+//estimated by finite differences), starting from the given weights. Returns
+//None when there are not enough reviews.
+pub fn optimize(weights: &[f64; 17], reviews: &[Review]) -> Option<Optimization> {
+    const ITERATIONS: usize = 200;
+    const LEARNING_RATE: f64 = 0.04;
+    const EPSILON: f64 = 1e-4;
+    let histories = item_histories(reviews);
+    let (loss_before, predictions) = loss(weights, &histories);
+    if predictions < MINIMAL_PREDICTIONS {
+        return None;
+    }
+    let mut current = *weights;
+    let mut best = (*weights, loss_before);
+    let mut first_moment = [0.0; 17];
+    let mut second_moment = [0.0; 17];
+    for iteration in 1..=ITERATIONS {
+        for index in 0..17 {
+            let mut shifted = current;
+            shifted[index] = current[index] + EPSILON;
+            let above = loss(&shifted, &histories).0;
+            shifted[index] = current[index] - EPSILON;
+            let below = loss(&shifted, &histories).0;
+            let gradient = (above - below) / (2.0 * EPSILON);
+            first_moment[index] = 0.9 * first_moment[index] + 0.1 * gradient;
+            second_moment[index] = 0.999 * second_moment[index] + 0.001 * gradient * gradient;
+            let corrected_first = first_moment[index] / (1.0 - 0.9f64.powi(iteration as i32));
+            let corrected_second = second_moment[index] / (1.0 - 0.999f64.powi(iteration as i32));
+            let (minimum, maximum) = WEIGHT_BOUNDS[index];
+            current[index] = (current[index]
+                - LEARNING_RATE * corrected_first / (corrected_second.sqrt() + 1e-8))
+                .clamp(minimum, maximum);
+        }
+        let current_loss = loss(&current, &histories).0;
+        if current_loss < best.1 {
+            best = (current, current_loss);
+        }
+    }
+    Some(Optimization {
+        weights: best.0,
+        loss_before,
+        loss_after: best.1,
+        predictions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn first_reviews() {
+        let fsrs = Fsrs::new(FsrsParameters::default());
+        let now = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let good = fsrs.next(&Schedule::default(), Grade::Good, now);
+        assert_eq!(good.stability, DEFAULT_WEIGHTS[2]);
+        assert_eq!(good.interval, 4.0);
+        // Reviewed on time, the stability grows
+        let later = good.due.unwrap();
+        let second = fsrs.next(&good, Grade::Good, later);
+        assert!(second.stability > 3.0 * good.stability);
+        assert!((retrievability(good.interval, good.stability) - 0.9).abs() < 0.01);
+        let forgotten = fsrs.next(&second, Grade::Again, second.due.unwrap());
+        assert!(forgotten.stability < second.stability);
+        assert!(forgotten.difficulty > second.difficulty);
+        assert_eq!(forgotten.lapses, 1);
+    }
+
+    #[test]
+    fn optimizer_fits_the_log() {
+        // A learner who forgets everything after a week, remembers before
+        let start = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let mut reviews = Vec::new();
+        for card_id in 0..40 {
+            for (review, elapsed) in [0, 2 + card_id % 3, 9 + card_id % 5].iter().enumerate() {
+                let grade = if review == 2 {
+                    Grade::Again
+                } else {
+                    Grade::Good
+                };
+                reviews.push(Review {
+                    item: ReviewItem {
+                        card_id,
+                        ordinal: 0,
+                    },
+                    time: start + days(*elapsed as f64),
+                    grade,
+                    time_taken: Duration::zero(),
+                    change: None,
+                });
+            }
+        }
+        let optimization = optimize(&DEFAULT_WEIGHTS, &reviews).unwrap();
+        assert_eq!(optimization.predictions, 80);
+        assert!(optimization.loss_after < optimization.loss_before);
+        assert!(optimize(&DEFAULT_WEIGHTS, &reviews[..30]).is_none());
+    }
+}
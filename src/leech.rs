@@ -0,0 +1,68 @@
+// Leeches are cards forgotten again and again, they take much of the study
+// time for little gain. An item becomes a leech when its lapses reach the
+// threshold of the deck, then again every half threshold. Its card is tagged
+// "leech" and suspended if the deck asks for it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::card::Card;
+
+pub const LEECH_TAG: &str = "leech";
+
+// [leech] table of the deck file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LeechSettings {
+    pub threshold: u32, // 0 disables the detection
+    pub suspend: bool,
+}
+
+impl Default for LeechSettings {
+    fn default() -> LeechSettings {
+        LeechSettings {
+            threshold: 8,
+            suspend: false,
+        }
+    }
+}
+
+impl LeechSettings {
+    //Whether an item that just lapsed, with this many lapses, is a leech
+    pub fn is_leech(&self, lapses: u32) -> bool {
+        if self.threshold == 0 || lapses < self.threshold {
+            return false;
+        }
+        (lapses - self.threshold).is_multiple_of((self.threshold / 2).max(1))
+    }
+
+    pub fn mark(&self, card: &mut Card) {
+        card.add_tag(LEECH_TAG);
+        if self.suspend {
+            card.suspended = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leech_threshold() {
+        let settings = LeechSettings::default();
+        let leeches: Vec<u32> = (0..20)
+            .filter(|lapses| settings.is_leech(*lapses))
+            .collect();
+        assert_eq!(leeches, vec![8, 12, 16]);
+        let disabled = LeechSettings {
+            threshold: 0,
+            suspend: true,
+        };
+        assert!(!disabled.is_leech(8));
+        let mut card = Card::default();
+        disabled.mark(&mut card);
+        disabled.mark(&mut card);
+        assert_eq!(card.tags, vec![String::from(LEECH_TAG)]);
+        assert!(card.suspended);
+    }
+}
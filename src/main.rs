@@ -1,13 +1,41 @@
 use color_eyre::Result;
 
+mod answer;
 mod app;
+mod card;
+mod cli;
+mod cloze;
+mod config;
+mod custom_study;
+mod deck;
+mod external_editor;
+mod forecast;
+mod formula;
+mod fsrs;
+mod leech;
 mod modes;
+mod multiple_choice;
+mod occlusion;
+mod ordered_list;
 mod popup;
+mod reschedule;
+mod review_log;
 mod rope;
+mod scheduler;
+mod session;
+mod simulator;
+mod snippet;
+mod spell;
+mod study_queue;
 mod term;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    //commands given in the shell are run without the TUI (see cli.rs)
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args);
+    }
     //creates a new crossterm terminal instance
     if let Ok(mut terminal) = term::init() {
         //creates a new app instance (see app.rs for more info)
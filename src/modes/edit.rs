@@ -1,20 +1,509 @@
-use crate::app::Message;
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
 
-use crossterm::event::{KeyCode, KeyEvent};
+use crate::card::Card;
+use crate::config::{Config, EditorConfig, LineNumbers};
+use crate::popup::Popup;
+use crate::rope::{MarkId, Rope};
+use crate::snippet::{ActiveSnippet, Snippets};
+use crate::spell::{self, SpellChecker};
 
-pub struct EditApp {}
+use crossterm::event::KeyEvent;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Widget},
+};
 
-enum EditMode {
-    Normal,
-    Append,
-    Visual,
+// The vim-like keys, apart from the buffer and its viewport
+mod modal;
+
+use modal::{line_start, EditMode};
+
+const LEAF_WEIGHT: u8 = 64;
+const MAX_SPELLING_SUGGESTIONS: usize = 4;
+
+pub struct EditApp {
+    card: Card,
+    field: CardField,
+    buffer: Rope,  // Text of the field being edited
+    cursor: usize, // Byte index in the buffer
+    // Cursors added with Ctrl-n or a block insert, they follow the edits
+    cursors: Vec<MarkId>,
+    // Length of the text selected after each cursor by Ctrl-n
+    selection_length: usize,
+    // The extra cursors come from a visual block and end with insert mode
+    block_insert: bool,
+    visual_start: usize,
+    mode: EditMode,
+    config: EditorConfig,
+    snippets: Snippets,
+    active_snippet: Option<ActiveSnippet>,
+    // The default text of the current tab stop is replaced by the next key
+    placeholder_selected: bool,
+    spell_checker: Option<SpellChecker>,
+    // Misspelled words and the text they were found in, checked again only
+    // when the text changes
+    misspelled: RefCell<(String, Vec<Range<usize>>)>,
+    // First key of a two keys command like "z="
+    pending_key: Option<char>,
+    current_popup: Option<Popup<EditPopupTypes>>,
+    // Viewport, updated while rendering to keep the cursor visible
+    top_row: Cell<usize>,
+    left_column: Cell<usize>,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum CardField {
+    Front,
+    Back,
+}
+
+enum EditPopupTypes {
+    // Misspelled word and the suggestions shown as buttons
+    Spelling(Range<usize>, Vec<String>),
+    NoSuggestion,
+}
+
+// A line of the buffer as shown on screen, one line can take several rows
+// when soft wrapping is enabled
+struct Row {
+    line_number: usize,
+    first_of_line: bool,
+    last_of_line: bool,
+    range: Range<usize>, // Bytes of the buffer shown on this row
 }
 
 impl EditApp {
-    pub fn new() -> EditApp {
-        EditApp {}
+    pub fn new(config: &Config, spell_checker: Option<SpellChecker>) -> EditApp {
+        EditApp {
+            card: Card::default(),
+            field: CardField::Front,
+            buffer: Rope::new(),
+            cursor: 0,
+            cursors: Vec::new(),
+            selection_length: 0,
+            block_insert: false,
+            visual_start: 0,
+            mode: EditMode::Normal,
+            config: config.editor,
+            snippets: Snippets::new(config.snippets.clone()),
+            active_snippet: None,
+            placeholder_selected: false,
+            spell_checker,
+            misspelled: RefCell::new((String::new(), Vec::new())),
+            pending_key: None,
+            current_popup: None,
+            top_row: Cell::new(0),
+            left_column: Cell::new(0),
+        }
+    }
+
+    //Card with the modifications of the buffer
+    pub fn card(&mut self) -> &Card {
+        self.store_buffer();
+        &self.card
     }
-    pub fn handle_key_press(&mut self, key: KeyEvent) -> Message {
-        todo!()
+
+    pub fn set_card(&mut self, card: Card) {
+        self.card = card;
+        self.load_field(self.field);
+    }
+
+    fn store_buffer(&mut self) {
+        let text = self.buffer.to_string();
+        match self.field {
+            CardField::Front => self.card.front = text,
+            CardField::Back => self.card.back = text,
+        }
+    }
+
+    fn load_field(&mut self, field: CardField) {
+        self.field = field;
+        let text = match field {
+            CardField::Front => self.card.front.clone(),
+            CardField::Back => self.card.back.clone(),
+        };
+        self.buffer = Rope::string_to_rope(text, LEAF_WEIGHT);
+        self.cursor = 0;
+        self.cursors.clear();
+        self.selection_length = 0;
+        self.mode = EditMode::Normal;
+        self.active_snippet = None;
+        self.top_row.set(0);
+        self.left_column.set(0);
+    }
+
+    fn switch_field(&mut self) {
+        self.store_buffer();
+        match self.field {
+            CardField::Front => self.load_field(CardField::Back),
+            CardField::Back => self.load_field(CardField::Front),
+        }
+    }
+
+    fn handle_popup_key_press(&mut self, key: KeyEvent) {
+        let popup = self.current_popup.as_mut().unwrap();
+        let result = popup.handle_key_press(key);
+        if result == popup.number_of_buttons {
+            return;
+        }
+        let popup_type = self.current_popup.take().unwrap().popup_type;
+        match popup_type {
+            EditPopupTypes::Spelling(word, suggestions) => {
+                // The last button keeps the word as it is
+                if let Some(suggestion) = suggestions.get(result as usize) {
+                    self.buffer.delete(word.clone());
+                    self.buffer.insert(word.start, suggestion);
+                    self.cursor = word.start;
+                    self.selection_length = 0;
+                }
+            }
+            EditPopupTypes::NoSuggestion => {}
+        }
+    }
+
+    //z= : proposes corrections for the word under the cursor
+    fn suggest_spelling(&mut self) {
+        let Some(spell_checker) = self.spell_checker.as_ref() else {
+            return;
+        };
+        let text = self.buffer.to_string();
+        let Some(word) = spell::prose_words(&text)
+            .into_iter()
+            .find(|word| word.contains(&self.cursor))
+        else {
+            return;
+        };
+        let mut suggestions = spell_checker.suggest(&text[word.clone()]);
+        suggestions.truncate(MAX_SPELLING_SUGGESTIONS);
+        self.current_popup = Some(if suggestions.is_empty() {
+            Popup::new(
+                EditPopupTypes::NoSuggestion,
+                format!("No suggestion for \"{}\"", &text[word]),
+                vec![String::from("OK")],
+            )
+        } else {
+            let mut buttons = suggestions.clone();
+            buttons.push(String::from("KEEP"));
+            Popup::new(
+                EditPopupTypes::Spelling(word.clone(), suggestions),
+                format!("Replace \"{}\" with :", &text[word]),
+                buttons,
+            )
+        });
+    }
+}
+
+//Splits a line in rows of at most width characters. Rows are broken after
+//whitespace when possible and never in the middle of a LaTeX command.
+fn wrap_line(line: &str, width: usize) -> Vec<Range<usize>> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+    let mut row_start = 0;
+    let mut last_space_break: Option<usize> = None;
+    for token in latex_tokens(line) {
+        let token_width = line[token.clone()].chars().count();
+        while line[row_start..token.start].chars().count() + token_width > width
+            && row_start < token.start
+        {
+            let row_end = match last_space_break {
+                Some(position) if position > row_start => position,
+                _ => token.start,
+            };
+            rows.push(row_start..row_end);
+            row_start = row_end;
+            last_space_break = None;
+        }
+        if token_width > width {
+            // Only a command longer than the whole row gets cut
+            let mut chunk_start = token.start;
+            for (count, (position, _)) in line[token.clone()].char_indices().enumerate() {
+                if count > 0 && count % width == 0 {
+                    rows.push(chunk_start..token.start + position);
+                    chunk_start = token.start + position;
+                }
+            }
+            row_start = chunk_start;
+        }
+        if line[token.clone()].chars().all(char::is_whitespace) {
+            last_space_break = Some(token.end);
+        }
+    }
+    rows.push(row_start..line.len());
+    rows
+}
+
+//Byte ranges of the units of a line that can't be split: a LaTeX command
+//("\frac", "\,") or a single character
+fn latex_tokens(line: &str) -> Vec<Range<usize>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if c == '\\' {
+            if let Some(&(_, next)) = chars.peek() {
+                if next.is_ascii_alphabetic() {
+                    while let Some(&(position, next)) = chars.peek() {
+                        if !next.is_ascii_alphabetic() {
+                            break;
+                        }
+                        end = position + next.len_utf8();
+                        chars.next();
+                    }
+                } else {
+                    end += next.len_utf8();
+                    chars.next();
+                }
+            }
+        }
+        tokens.push(start..end);
+    }
+    tokens
+}
+
+impl EditApp {
+    fn rows(&self, text: &str, text_width: usize) -> Vec<Row> {
+        let mut rows = Vec::new();
+        let mut line_start = 0;
+        for (line_number, line) in text.split('\n').enumerate() {
+            let line_rows = if self.config.soft_wrap {
+                wrap_line(line, text_width)
+            } else {
+                // Without wrapping a line is a single row scrolled horizontally
+                let whole_line = 0..line.len();
+                vec![whole_line]
+            };
+            let number_of_rows = line_rows.len();
+            for (index, range) in line_rows.into_iter().enumerate() {
+                rows.push(Row {
+                    line_number,
+                    first_of_line: index == 0,
+                    last_of_line: index == number_of_rows - 1,
+                    range: line_start + range.start..line_start + range.end,
+                });
+            }
+            line_start += line.len() + 1;
+        }
+        rows
+    }
+
+    //Moves the viewport so the cursor row stays at scrolloff rows of the borders
+    fn scroll_to_cursor(&self, cursor_row: usize, height: usize) {
+        let scrolloff = (self.config.scrolloff as usize).min(height.saturating_sub(1) / 2);
+        let mut top_row = self.top_row.get();
+        if cursor_row < top_row + scrolloff {
+            top_row = cursor_row.saturating_sub(scrolloff);
+        }
+        if cursor_row + scrolloff >= top_row + height {
+            top_row = cursor_row + scrolloff + 1 - height;
+        }
+        self.top_row.set(top_row);
+    }
+
+    fn gutter_width(&self, number_of_lines: usize) -> usize {
+        if self.config.line_numbers == LineNumbers::None {
+            0
+        } else {
+            number_of_lines.to_string().len().max(2) + 1
+        }
+    }
+
+    fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        let Some(spell_checker) = self.spell_checker.as_ref() else {
+            return Vec::new();
+        };
+        let mut misspelled = self.misspelled.borrow_mut();
+        if misspelled.0 != text {
+            *misspelled = (text.to_string(), spell_checker.misspelled(text));
+        }
+        misspelled.1.clone()
+    }
+
+    fn title(&self) -> &str {
+        match self.field {
+            CardField::Front => "Front",
+            CardField::Back => "Back",
+        }
+    }
+}
+
+impl Widget for &EditApp {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let mode_name = if self.cursors.is_empty() {
+            self.mode_name().to_string()
+        } else {
+            format!("{} ({} cursors)", self.mode_name(), self.cursors.len() + 1)
+        };
+        let block = Block::bordered()
+            .title(self.title())
+            .title_bottom(Line::from(mode_name).right_aligned());
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if inner.width == 0 || inner.height == 0 {
+            return;
+        }
+
+        let text = self.buffer.to_string();
+        let number_of_lines = text.split('\n').count();
+        let gutter_width = self.gutter_width(number_of_lines);
+        let text_width = (inner.width as usize).saturating_sub(gutter_width).max(1);
+        let rows = self.rows(&text, text_width);
+
+        let cursor_row = rows
+            .iter()
+            .position(|row| {
+                row.range.contains(&self.cursor)
+                    || (row.last_of_line && row.range.end == self.cursor)
+            })
+            .unwrap_or(0);
+        let cursor_line = rows[cursor_row].line_number;
+        let cursor_column = text[rows[cursor_row].range.start..self.cursor]
+            .chars()
+            .count();
+        self.scroll_to_cursor(cursor_row, inner.height as usize);
+        if !self.config.soft_wrap {
+            let mut left_column = self.left_column.get();
+            if cursor_column < left_column {
+                left_column = cursor_column;
+            } else if cursor_column >= left_column + text_width {
+                left_column = cursor_column + 1 - text_width;
+            }
+            self.left_column.set(left_column);
+        }
+        let left_column = if self.config.soft_wrap {
+            0
+        } else {
+            self.left_column.get()
+        };
+
+        let selection = match self.active_snippet.as_ref() {
+            _ if self.mode == EditMode::Visual => self.visual_selection(),
+            Some(active_snippet) if self.placeholder_selected => active_snippet.current_range(),
+            _ => 0..0,
+        };
+        let gutter_style = Style::new().fg(Color::DarkGray);
+        let selection_style = Style::new().bg(Color::DarkGray);
+        let misspelled_style = Style::new()
+            .fg(Color::Red)
+            .add_modifier(Modifier::UNDERLINED);
+        let misspelled = self.misspelled(&text);
+        let cursor_style = Style::new().add_modifier(Modifier::REVERSED);
+        let extra_cursors: Vec<usize> = self
+            .cursors
+            .iter()
+            .filter_map(|mark| self.buffer.mark(*mark))
+            .collect();
+        // Words selected by Ctrl-n
+        let matches: Vec<Range<usize>> = std::iter::once(self.cursor)
+            .chain(extra_cursors.iter().copied())
+            .filter(|_| self.selection_length > 0)
+            .map(|position| position..position + self.selection_length)
+            .collect();
+        let block = (self.mode == EditMode::VisualBlock).then(|| self.block(&text));
+
+        for (screen_row, row) in rows
+            .iter()
+            .skip(self.top_row.get())
+            .take(inner.height as usize)
+            .enumerate()
+        {
+            let y = inner.y + screen_row as u16;
+            if gutter_width > 0 && row.first_of_line {
+                let number = match self.config.line_numbers {
+                    LineNumbers::Relative if row.line_number != cursor_line => {
+                        row.line_number.abs_diff(cursor_line)
+                    }
+                    _ => row.line_number + 1,
+                };
+                buf.set_string(
+                    inner.x,
+                    y,
+                    format!("{:>width$} ", number, width = gutter_width - 1),
+                    gutter_style,
+                );
+            }
+            let x = inner.x + gutter_width as u16;
+            let row_column = text[line_start(&text, row.range.start)..row.range.start]
+                .chars()
+                .count();
+            let in_block = |column: usize| {
+                block.as_ref().is_some_and(|(lines, left, right)| {
+                    lines.contains(&row.line_number) && (*left..=*right).contains(&column)
+                })
+            };
+            for (index, (position, c)) in text[row.range.clone()]
+                .char_indices()
+                .enumerate()
+                .skip(left_column)
+                .take(text_width)
+            {
+                let position = row.range.start + position;
+                let mut style = if selection.contains(&position)
+                    || in_block(row_column + index)
+                    || matches.iter().any(|word| word.contains(&position))
+                {
+                    selection_style
+                } else if misspelled.iter().any(|word| word.contains(&position)) {
+                    misspelled_style
+                } else {
+                    Style::new()
+                };
+                if extra_cursors.contains(&position) {
+                    style = style.patch(cursor_style);
+                }
+                buf.set_string(x + (index - left_column) as u16, y, c.to_string(), style);
+            }
+            // Extra cursors after the last character of a line
+            let row_length = text[row.range.clone()].chars().count();
+            if row.last_of_line
+                && extra_cursors.contains(&row.range.end)
+                && row_length >= left_column
+                && row_length - left_column < text_width
+            {
+                let position = (x + (row_length - left_column) as u16, y);
+                if let Some(cell) = buf.cell_mut(position) {
+                    cell.set_style(cursor_style);
+                }
+            }
+        }
+
+        let screen_row = cursor_row.checked_sub(self.top_row.get());
+        if let Some(screen_row) = screen_row.filter(|row| *row < inner.height as usize) {
+            let column = (cursor_column - left_column).min(text_width - 1);
+            let position = (
+                inner.x + (gutter_width + column) as u16,
+                inner.y + screen_row as u16,
+            );
+            if let Some(cell) = buf.cell_mut(position) {
+                cell.set_style(cursor_style);
+            }
+        }
+
+        if let Some(popup) = self.current_popup.as_ref() {
+            popup.render(
+                Popup::<EditPopupTypes>::make_centered_rectangle_area(60, 40, area),
+                buf,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_keeps_latex_commands() {
+        let line = "a \\frac{1}{2} \\alpha";
+        let rows: Vec<&str> = wrap_line(line, 8)
+            .into_iter()
+            .map(|range| &line[range])
+            .collect();
+        assert_eq!(rows, vec!["a ", "\\frac{1}", "{2} ", "\\alpha"]);
+        assert_eq!(wrap_line("", 8), vec![0..0]);
+        assert_eq!(wrap_line("\\longcommand", 4).len(), 3);
     }
 }
@@ -0,0 +1,699 @@
+// Vim-like modal editing of the buffer: motions, the Normal, Insert, Visual
+// and Visual block modes, the extra cursors and the snippets of Insert mode.
+
+use std::ops::Range;
+
+use super::EditApp;
+use crate::app::{Message, Mode};
+use crate::snippet::ActiveSnippet;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub(super) enum EditMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualBlock,
+}
+
+impl EditApp {
+    pub fn handle_key_press(&mut self, key: KeyEvent) -> Message {
+        if self.current_popup.is_some() {
+            self.handle_popup_key_press(key);
+            return Message::Nothing;
+        }
+        match self.mode {
+            EditMode::Normal => return self.handle_normal_key_press(key),
+            EditMode::Insert => self.handle_insert_key_press(key),
+            EditMode::Visual => self.handle_visual_key_press(key),
+            EditMode::VisualBlock => self.handle_visual_block_key_press(key),
+        }
+        Message::Nothing
+    }
+
+    fn handle_normal_key_press(&mut self, key: KeyEvent) -> Message {
+        use KeyCode::*;
+        if let Some(pending_key) = self.pending_key.take() {
+            if pending_key == 'z' && key.code == Char('=') {
+                self.suggest_spelling();
+            }
+            return Message::Nothing;
+        }
+        if self.handle_motion(key) {
+            self.clamp_normal_cursor();
+            return Message::Nothing;
+        }
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Char('v') if control => {
+                self.visual_start = self.cursor;
+                self.clear_cursors();
+                self.mode = EditMode::VisualBlock;
+            }
+            Char('n') if control => self.add_cursor_at_next_match(),
+            Char('i') => {
+                self.selection_length = 0;
+                self.mode = EditMode::Insert;
+            }
+            Char('a') => {
+                let selection_length = self.selection_length;
+                self.move_cursors(|text, position| {
+                    if selection_length > 0 {
+                        position + selection_length
+                    } else {
+                        right(text, position)
+                    }
+                });
+                self.selection_length = 0;
+                self.mode = EditMode::Insert;
+            }
+            Char('c') if self.selection_length > 0 => {
+                let selection_length = self.selection_length;
+                self.edit_at_cursors(|_, position| position..position + selection_length, "");
+                self.selection_length = 0;
+                self.mode = EditMode::Insert;
+            }
+            Char('I') => {
+                self.move_cursors(line_start);
+                self.selection_length = 0;
+                self.mode = EditMode::Insert;
+            }
+            Char('A') => {
+                self.move_cursors(line_end);
+                self.selection_length = 0;
+                self.mode = EditMode::Insert;
+            }
+            Char('v') => {
+                self.visual_start = self.cursor;
+                self.selection_length = 0;
+                self.mode = EditMode::Visual;
+            }
+            Char('z') => self.pending_key = Some('z'),
+            Char('x') | Delete => {
+                self.edit_at_cursors(
+                    |text, position| {
+                        if position < line_end(text, position) {
+                            position..next_boundary(text, position)
+                        } else {
+                            position..position
+                        }
+                    },
+                    "",
+                );
+                self.selection_length = 0;
+                self.clamp_normal_cursor();
+            }
+            Tab => self.switch_field(),
+            Char('o') => {
+                self.store_buffer();
+                return Message::OpenExternalEditor;
+            }
+            Esc if !self.cursors.is_empty() || self.selection_length > 0 => self.clear_cursors(),
+            Char('q') | Esc => {
+                self.store_buffer();
+                return Message::ChangeMode(Mode::SelectionCard);
+            }
+            _ => {}
+        }
+        Message::Nothing
+    }
+
+    fn handle_insert_key_press(&mut self, key: KeyEvent) {
+        use KeyCode::*;
+        if self.handle_motion(key) {
+            self.placeholder_selected = false;
+            return;
+        }
+        if !self.cursors.is_empty() && self.handle_multi_cursor_insert(key) {
+            return;
+        }
+        let text = self.buffer.to_string();
+        match key.code {
+            Esc => {
+                self.mode = EditMode::Normal;
+                self.active_snippet = None;
+                self.placeholder_selected = false;
+                self.move_cursors(left);
+                if self.block_insert {
+                    self.clear_cursors();
+                }
+            }
+            Tab => {
+                // Expanding a trigger has priority over jumping to the next tab stop
+                let expanded = self.expand_snippet(&text);
+                if !expanded {
+                    self.next_tab_stop();
+                }
+            }
+            BackTab if self.active_snippet.is_some() => {
+                self.active_snippet.as_mut().unwrap().previous();
+                self.jump_to_tab_stop();
+            }
+            Enter => self.insert("\n"),
+            Backspace if self.placeholder_selected => self.insert(""),
+            Backspace if self.cursor > 0 => {
+                self.replace(previous_boundary(&text, self.cursor)..self.cursor, "")
+            }
+            Delete if self.placeholder_selected => self.insert(""),
+            Delete if self.cursor < text.len() => {
+                self.replace(self.cursor..next_boundary(&text, self.cursor), "")
+            }
+            Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.insert(c.encode_utf8(&mut [0; 4]))
+            }
+            _ => {}
+        }
+    }
+
+    //Edits made at every cursor, returns false if the key is not an edit.
+    //Snippets are not expanded with several cursors.
+    fn handle_multi_cursor_insert(&mut self, key: KeyEvent) -> bool {
+        use KeyCode::*;
+        match key.code {
+            Enter => self.edit_at_cursors(|_, position| position..position, "\n"),
+            Backspace => self.edit_at_cursors(
+                |text, position| previous_boundary(text, position)..position,
+                "",
+            ),
+            Delete => {
+                self.edit_at_cursors(|text, position| position..next_boundary(text, position), "")
+            }
+            Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.edit_at_cursors(|_, position| position..position, c.encode_utf8(&mut [0; 4]))
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    //Replaces the trigger before the cursor by its snippet, returns false if
+    //there is no trigger
+    fn expand_snippet(&mut self, text: &str) -> bool {
+        let before_cursor = &text[line_start(text, self.cursor)..self.cursor];
+        let Some((trigger_length, snippet)) = self.snippets.find(before_cursor) else {
+            return false;
+        };
+        let start = self.cursor - trigger_length;
+        // A snippet inside a snippet is not tracked, the outer one ends
+        self.active_snippet = None;
+        self.replace(start..self.cursor, &snippet.text);
+        self.active_snippet = ActiveSnippet::new(&snippet, start);
+        if self.active_snippet.is_some() {
+            self.jump_to_tab_stop();
+        }
+        true
+    }
+
+    fn next_tab_stop(&mut self) {
+        if let Some(active_snippet) = self.active_snippet.as_mut() {
+            if active_snippet.next() {
+                self.jump_to_tab_stop();
+            } else {
+                self.active_snippet = None;
+            }
+        }
+    }
+
+    fn jump_to_tab_stop(&mut self) {
+        let active_snippet = self.active_snippet.as_ref().unwrap();
+        let range = active_snippet.current_range();
+        self.cursor = range.start;
+        self.placeholder_selected = !range.is_empty();
+        if active_snippet.is_last() {
+            self.active_snippet = None;
+            self.cursor = range.end;
+            self.placeholder_selected = false;
+        }
+    }
+
+    //Replaces the bytes of the range by the text and puts the cursor after it
+    fn replace(&mut self, range: Range<usize>, text: &str) {
+        self.buffer.delete(range.clone());
+        self.buffer.insert(range.start, text);
+        self.cursor = range.start + text.len();
+        if let Some(active_snippet) = self.active_snippet.as_mut() {
+            if active_snippet.text_replaced(range, text.len()) {
+                // The mirrors can be before the cursor and move it
+                let offset = self.cursor - active_snippet.current_range().start;
+                active_snippet.update_mirrors(&mut self.buffer);
+                self.cursor = active_snippet.current_range().start + offset;
+            } else {
+                self.active_snippet = None;
+            }
+        }
+    }
+
+    fn handle_visual_block_key_press(&mut self, key: KeyEvent) {
+        use KeyCode::*;
+        if self.handle_motion(key) {
+            self.clamp_normal_cursor();
+            return;
+        }
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            Char('I') => self.start_block_insert(false),
+            Char('A') => self.start_block_insert(true),
+            Char('d') | Char('x') | Delete => {
+                let text = self.buffer.to_string();
+                let (lines, left_column, right_column) = self.block(&text);
+                let first_line = lines.start;
+                // From the bottom so the positions of the lines above stay valid
+                for line in lines.rev() {
+                    let text = self.buffer.to_string();
+                    let start = position_of(&text, line, left_column);
+                    let end = position_of(&text, line, right_column + 1);
+                    self.buffer.delete(start..end);
+                }
+                let text = self.buffer.to_string();
+                self.cursor = position_of(&text, first_line, left_column);
+                self.visual_start = self.cursor;
+                self.mode = EditMode::Normal;
+                self.clamp_normal_cursor();
+            }
+            Char('v') if control => self.mode = EditMode::Normal,
+            Esc => self.mode = EditMode::Normal,
+            _ => {}
+        }
+    }
+
+    //Lines of the visual block and its first and last columns
+    pub(super) fn block(&self, text: &str) -> (Range<usize>, usize, usize) {
+        let (start_line, start_column) = line_and_column(text, self.visual_start);
+        let (cursor_line, cursor_column) = line_and_column(text, self.cursor);
+        (
+            start_line.min(cursor_line)..start_line.max(cursor_line) + 1,
+            start_column.min(cursor_column),
+            start_column.max(cursor_column),
+        )
+    }
+
+    //I and A of a visual block: one cursor per line of the block, before
+    //(or after) the block. Lines too short for I are skipped, lines too short
+    //for A are filled with spaces like in vim.
+    fn start_block_insert(&mut self, after: bool) {
+        let text = self.buffer.to_string();
+        let (lines, left_column, right_column) = self.block(&text);
+        let column = if after { right_column + 1 } else { left_column };
+        self.clear_cursors();
+        let mut positions = Vec::new();
+        for line in lines.rev() {
+            let text = self.buffer.to_string();
+            let length = line_length(&text, line);
+            if length < column && !after {
+                continue;
+            }
+            if length < column {
+                let end = position_of(&text, line, length);
+                self.buffer.insert(end, &" ".repeat(column - length));
+            }
+            let text = self.buffer.to_string();
+            positions.push(self.buffer.add_mark(position_of(&text, line, column)));
+        }
+        // The first line of the block holds the main cursor
+        if let Some(first) = positions.pop() {
+            self.cursor = self.buffer.mark(first).unwrap();
+            self.buffer.remove_mark(first);
+        }
+        self.cursors = positions;
+        self.block_insert = true;
+        self.mode = EditMode::Insert;
+    }
+
+    //Ctrl-n : the first press selects the word under the cursor, the next ones
+    //add a cursor on the next occurrence of that word
+    fn add_cursor_at_next_match(&mut self) {
+        let text = self.buffer.to_string();
+        if self.selection_length == 0 {
+            // Outside of a word, like on the "&" of an alignment, the character is used
+            let word = word_at(&text, self.cursor)
+                .unwrap_or(self.cursor..next_boundary(&text, self.cursor));
+            if !word.is_empty() {
+                self.clear_cursors();
+                self.cursor = word.start;
+                self.selection_length = word.len();
+            }
+            return;
+        }
+        let Some(word) = text.get(self.cursor..self.cursor + self.selection_length) else {
+            self.selection_length = 0;
+            return;
+        };
+        let positions: Vec<usize> = std::iter::once(self.cursor)
+            .chain(
+                self.cursors
+                    .iter()
+                    .filter_map(|mark| self.buffer.mark(*mark)),
+            )
+            .collect();
+        let from = positions.last().unwrap() + self.selection_length;
+        let next = text[from..]
+            .find(word)
+            .map(|found| from + found)
+            .or_else(|| text.find(word));
+        if let Some(next) = next.filter(|next| !positions.contains(next)) {
+            let mark = self.buffer.add_mark(next);
+            self.cursors.push(mark);
+        }
+    }
+
+    fn clear_cursors(&mut self) {
+        for mark in self.cursors.drain(..) {
+            self.buffer.remove_mark(mark);
+        }
+        self.selection_length = 0;
+        self.block_insert = false;
+    }
+
+    //Moves the main cursor and the extra cursors
+    fn move_cursors(&mut self, target: impl Fn(&str, usize) -> usize) {
+        let text = self.buffer.to_string();
+        self.cursor = target(&text, self.cursor);
+        for mark in self.cursors.iter() {
+            if let Some(position) = self.buffer.mark(*mark) {
+                self.buffer.set_mark(*mark, target(&text, position));
+            }
+        }
+    }
+
+    //Replaces at each cursor the range given by the function with the text,
+    //every cursor ends after its inserted text
+    fn edit_at_cursors(&mut self, range: impl Fn(&str, usize) -> Range<usize>, inserted: &str) {
+        let main_cursor = self.buffer.add_mark(self.cursor);
+        for mark in std::iter::once(main_cursor).chain(self.cursors.clone()) {
+            let text = self.buffer.to_string();
+            let Some(position) = self.buffer.mark(mark) else {
+                continue;
+            };
+            let edited = range(&text, position);
+            self.buffer.delete(edited.clone());
+            self.buffer.insert(edited.start, inserted);
+        }
+        self.cursor = self.buffer.mark(main_cursor).unwrap();
+        self.buffer.remove_mark(main_cursor);
+    }
+
+    fn handle_visual_key_press(&mut self, key: KeyEvent) {
+        use KeyCode::*;
+        if self.handle_motion(key) {
+            self.clamp_normal_cursor();
+            return;
+        }
+        match key.code {
+            Char('d') | Char('x') | Delete => {
+                let selection = self.visual_selection();
+                self.cursor = selection.start;
+                self.buffer.delete(selection);
+                self.mode = EditMode::Normal;
+                self.clamp_normal_cursor();
+            }
+            Char('v') | Esc => self.mode = EditMode::Normal,
+            _ => {}
+        }
+    }
+
+    //Moves the cursors if the key is a motion, returns false otherwise
+    fn handle_motion(&mut self, key: KeyEvent) -> bool {
+        let letters = self.mode != EditMode::Insert;
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return false;
+        }
+        let Some(target) = motion(key, letters) else {
+            return false;
+        };
+        self.move_cursors(target);
+        self.selection_length = 0;
+        true
+    }
+
+    //Inserts at the cursor, or in place of the selected placeholder
+    fn insert(&mut self, inserted: &str) {
+        let range = match self.active_snippet.as_ref() {
+            Some(active_snippet) if self.placeholder_selected => active_snippet.current_range(),
+            _ => self.cursor..self.cursor,
+        };
+        self.placeholder_selected = false;
+        self.replace(range, inserted);
+    }
+
+    //Outside of insert mode the cursors stay on a character, like in vim
+    fn clamp_normal_cursor(&mut self) {
+        self.move_cursors(|text, mut position| {
+            position = position.min(text.len());
+            while !text.is_char_boundary(position) {
+                position -= 1;
+            }
+            if position == line_end(text, position) && position > line_start(text, position) {
+                position = previous_boundary(text, position);
+            }
+            position
+        });
+    }
+
+    pub(super) fn visual_selection(&self) -> Range<usize> {
+        let text = self.buffer.to_string();
+        let start = self.visual_start.min(self.cursor);
+        let end = self.visual_start.max(self.cursor);
+        start..next_boundary(&text, end)
+    }
+
+    pub(super) fn mode_name(&self) -> &str {
+        match self.mode {
+            EditMode::Normal => "NORMAL",
+            EditMode::Insert => "INSERT",
+            EditMode::Visual => "VISUAL",
+            EditMode::VisualBlock => "V-BLOCK",
+        }
+    }
+}
+
+//Function moving a cursor for the motion keys
+fn motion(key: KeyEvent, letters: bool) -> Option<fn(&str, usize) -> usize> {
+    use KeyCode::*;
+    Some(match key.code {
+        Left => left,
+        Right => right,
+        Up => up,
+        Down => down,
+        Home => line_start,
+        End => line_end,
+        Char('h') if letters => left,
+        Char('l') if letters => right,
+        Char('k') if letters => up,
+        Char('j') if letters => down,
+        Char('0') if letters => line_start,
+        Char('$') if letters => line_end,
+        _ => return None,
+    })
+}
+
+fn left(text: &str, index: usize) -> usize {
+    if index > line_start(text, index) {
+        previous_boundary(text, index)
+    } else {
+        index
+    }
+}
+
+fn right(text: &str, index: usize) -> usize {
+    if index < line_end(text, index) {
+        next_boundary(text, index)
+    } else {
+        index
+    }
+}
+
+fn up(text: &str, index: usize) -> usize {
+    vertical_move(text, index, false)
+}
+
+fn down(text: &str, index: usize) -> usize {
+    vertical_move(text, index, true)
+}
+
+//Word (with its eventual LaTeX backslash) containing the index
+fn word_at(text: &str, index: usize) -> Option<Range<usize>> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    if !text[index..].chars().next().is_some_and(is_word) {
+        return None;
+    }
+    let start = text[..index]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(index, |(position, _)| position);
+    let start = if text[..start].ends_with('\\') {
+        start - 1
+    } else {
+        start
+    };
+    let end = text[index..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(text.len(), |(position, _)| index + position);
+    Some(start..end)
+}
+
+//Line number and column (in characters) of a byte index
+fn line_and_column(text: &str, index: usize) -> (usize, usize) {
+    let start = line_start(text, index);
+    (
+        text[..start].matches('\n').count(),
+        text[start..index].chars().count(),
+    )
+}
+
+fn line_length(text: &str, line: usize) -> usize {
+    text.split('\n')
+        .nth(line)
+        .map_or(0, |line| line.chars().count())
+}
+
+//Byte index of a column of a line, or the end of the line if it is too short
+fn position_of(text: &str, line: usize, column: usize) -> usize {
+    let start = if line == 0 {
+        0
+    } else {
+        text.match_indices('\n')
+            .nth(line - 1)
+            .map_or(text.len(), |(position, _)| position + 1)
+    };
+    let end = line_end(text, start);
+    text[start..end]
+        .char_indices()
+        .nth(column)
+        .map_or(end, |(position, _)| start + position)
+}
+
+pub(super) fn line_start(text: &str, index: usize) -> usize {
+    text[..index].rfind('\n').map_or(0, |position| position + 1)
+}
+
+fn line_end(text: &str, index: usize) -> usize {
+    text[index..]
+        .find('\n')
+        .map_or(text.len(), |position| index + position)
+}
+
+fn previous_boundary(text: &str, index: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(position, _)| position)
+}
+
+fn next_boundary(text: &str, index: usize) -> usize {
+    text[index..]
+        .chars()
+        .next()
+        .map_or(index, |c| index + c.len_utf8())
+}
+
+//Moves to the same column on the next or previous line
+fn vertical_move(text: &str, index: usize, down: bool) -> usize {
+    let start = line_start(text, index);
+    let column = text[start..index].chars().count();
+    let target_start = if down {
+        let end = line_end(text, index);
+        if end == text.len() {
+            return index;
+        }
+        end + 1
+    } else {
+        if start == 0 {
+            return index;
+        }
+        line_start(text, start - 1)
+    };
+    let target_end = line_end(text, target_start);
+    text[target_start..target_end]
+        .char_indices()
+        .nth(column)
+        .map_or(target_end, |(position, _)| target_start + position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::config::Config;
+
+    fn press(edit_app: &mut EditApp, keys: &[KeyEvent]) {
+        for key in keys {
+            edit_app.handle_key_press(*key);
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn control(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn block_insert_and_multi_cursor() {
+        let mut edit_app = EditApp::new(&Config::default(), None);
+        edit_app.set_card(Card::new(String::from("a & b\\\nc & d"), String::new()));
+        use KeyCode::*;
+        press(
+            &mut edit_app,
+            &[
+                control('v'),
+                key(Char('j')),
+                key(Char('A')),
+                key(Char('x')),
+                key(Esc),
+            ],
+        );
+        assert_eq!(edit_app.card().front, "ax & b\\\ncx & d");
+        press(
+            &mut edit_app,
+            &[
+                key(Char('0')),
+                key(Char('l')),
+                key(Char('l')),
+                key(Char('l')),
+                control('n'),
+                control('n'),
+                key(Char('c')),
+                key(Char('=')),
+                key(Esc),
+            ],
+        );
+        assert_eq!(edit_app.card().front, "ax = b\\\ncx = d");
+        // A block deleted up to the end of the text
+        edit_app.set_card(Card::new(String::from("abc\ndef"), String::new()));
+        press(
+            &mut edit_app,
+            &[
+                control('v'),
+                key(Char('j')),
+                key(Char('l')),
+                key(Char('l')),
+                key(Char('d')),
+            ],
+        );
+        assert_eq!(edit_app.card().front, "\n");
+        // The selection ends with an edit
+        edit_app.set_card(Card::new(String::from("ab"), String::new()));
+        press(
+            &mut edit_app,
+            &[control('n'), key(Char('x')), control('n'), control('n')],
+        );
+        assert_eq!(edit_app.card().front, "b");
+    }
+
+    #[test]
+    fn columns_and_words() {
+        let text = "ab\ncdé\n\\alpha_1 x";
+        assert_eq!(line_and_column(text, 5), (1, 2));
+        assert_eq!(position_of(text, 1, 2), 5);
+        assert_eq!(position_of(text, 1, 10), 7);
+        assert_eq!(position_of(text, 2, 0), 8);
+        assert_eq!(word_at(text, 11), Some(8..16));
+        assert_eq!(word_at(text, 16), None);
+    }
+}
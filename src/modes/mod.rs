@@ -1,3 +1,4 @@
 pub mod edit;
+pub mod occlusion;
 pub mod selection;
 pub mod testing;
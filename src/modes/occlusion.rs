@@ -0,0 +1,186 @@
+// Editor of the masks of an image occlusion card: masks are added in the
+// center of the image then moved and resized with the keyboard. The selected
+// mask is drawn in the color of the reviews, the others in gray.
+
+use std::cell::RefCell;
+
+use crate::app::{Message, Mode};
+use crate::card::Card;
+use crate::occlusion::{self, ImageView, Mask};
+
+use crossterm::event::{KeyCode, KeyEvent};
+use image::{DynamicImage, GenericImageView};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    text::Line,
+    widgets::{Block, Widget},
+};
+use ratatui_image::picker::Picker;
+
+pub struct OcclusionApp {
+    card: Card,
+    image: DynamicImage,
+    selected: usize, // Index of the selected mask
+    // Encoded while rendering, hence the RefCell
+    view: RefCell<ImageView>,
+}
+
+impl OcclusionApp {
+    pub fn new(picker: Picker) -> OcclusionApp {
+        OcclusionApp {
+            card: Card::default(),
+            image: DynamicImage::default(),
+            selected: 0,
+            view: RefCell::new(ImageView::new(picker)),
+        }
+    }
+
+    pub fn set_card(&mut self, mut card: Card, image: DynamicImage) {
+        let (width, height) = image.dimensions();
+        for mask in &mut card.masks {
+            *mask = mask.clamped(width, height);
+        }
+        self.card = card;
+        self.image = image;
+        self.selected = 0;
+        self.draw();
+    }
+
+    pub fn card(&self) -> &Card {
+        &self.card
+    }
+
+    //Draws the masks over the image shown
+    fn draw(&mut self) {
+        let masks: Vec<_> = self
+            .card
+            .masks
+            .iter()
+            .enumerate()
+            .map(|(index, mask)| {
+                let color = if index == self.selected {
+                    occlusion::ACTIVE_MASK_COLOR
+                } else {
+                    occlusion::MASK_COLOR
+                };
+                (*mask, color)
+            })
+            .collect();
+        let drawn = occlusion::draw_masks(&self.image, &masks);
+        self.view.get_mut().set_image(Some(drawn));
+    }
+
+    fn add_mask(&mut self) {
+        let (width, height) = self.image.dimensions();
+        self.card.masks.push(Mask::centered(width, height));
+        self.selected = self.card.masks.len() - 1;
+    }
+
+    //The schedule of the item of the mask goes with it, the following items
+    //keep theirs
+    fn delete_mask(&mut self) {
+        if self.selected >= self.card.masks.len() {
+            return;
+        }
+        self.card.masks.remove(self.selected);
+        if self.selected < self.card.schedules.len() {
+            self.card.schedules.remove(self.selected);
+        }
+        self.selected = self.selected.min(self.card.masks.len().saturating_sub(1));
+    }
+
+    fn change_mask(&mut self, change: impl Fn(Mask, u32, u32) -> Mask) {
+        let (width, height) = self.image.dimensions();
+        if let Some(mask) = self.card.masks.get_mut(self.selected) {
+            *mask = change(*mask, width, height);
+        }
+    }
+
+    pub fn handle_key_press(&mut self, key: KeyEvent) -> Message {
+        use KeyCode::*;
+        let count = self.card.masks.len();
+        match key.code {
+            Char('q') | Esc => return Message::ChangeMode(Mode::SelectionCard),
+            Char('n') | Char('a') => self.add_mask(),
+            Char('d') | Delete => self.delete_mask(),
+            Tab if count > 0 => self.selected = (self.selected + 1) % count,
+            BackTab if count > 0 => self.selected = (self.selected + count - 1) % count,
+            Char('h') | Left => self.change_mask(|mask, w, h| mask.moved(-1, 0, w, h)),
+            Char('l') | Right => self.change_mask(|mask, w, h| mask.moved(1, 0, w, h)),
+            Char('k') | Up => self.change_mask(|mask, w, h| mask.moved(0, -1, w, h)),
+            Char('j') | Down => self.change_mask(|mask, w, h| mask.moved(0, 1, w, h)),
+            Char('H') => self.change_mask(|mask, w, h| mask.resized(-1, 0, w, h)),
+            Char('L') => self.change_mask(|mask, w, h| mask.resized(1, 0, w, h)),
+            Char('K') => self.change_mask(|mask, w, h| mask.resized(0, -1, w, h)),
+            Char('J') => self.change_mask(|mask, w, h| mask.resized(0, 1, w, h)),
+            _ => return Message::Nothing,
+        }
+        self.draw();
+        Message::Nothing
+    }
+}
+
+impl Widget for &OcclusionApp {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let title = match self.card.masks.len() {
+            0 => String::from("Masks (none yet)"),
+            count => format!("Masks ({} / {})", self.selected + 1, count),
+        };
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let [image_area, controls_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(inner);
+        self.view.borrow_mut().render(image_area, buf);
+        Line::from("[n] New  [Tab] Next  [h/j/k/l] Move  [H/J/K/L] Resize  [d] Delete  [q] Save")
+            .centered()
+            .render(controls_area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Schedule;
+    use crossterm::event::KeyModifiers;
+    use image::RgbaImage;
+
+    #[test]
+    fn masks_are_edited_with_the_keyboard() {
+        let mut editor = OcclusionApp::new(Picker::from_fontsize((8, 16)));
+        let mut card = Card::default();
+        card.set_schedule(
+            1,
+            Schedule {
+                lapses: 3,
+                ..Schedule::default()
+            },
+        );
+        editor.set_card(card, DynamicImage::ImageRgba8(RgbaImage::new(100, 100)));
+        let mut press =
+            |code: KeyCode| editor.handle_key_press(KeyEvent::new(code, KeyModifiers::NONE));
+        for code in [
+            KeyCode::Char('n'),
+            KeyCode::Char('n'),
+            KeyCode::Char('l'),
+            KeyCode::Char('J'),
+            KeyCode::Tab,
+            KeyCode::Char('d'),
+        ] {
+            assert!(press(code) == Message::Nothing);
+        }
+        assert!(press(KeyCode::Char('q')) == Message::ChangeMode(Mode::SelectionCard));
+        let card = editor.card();
+        assert_eq!(
+            card.masks,
+            vec![Mask {
+                x: 39,
+                y: 37,
+                width: 25,
+                height: 27
+            }]
+        );
+        assert_eq!(card.schedule(0).lapses, 3);
+    }
+}
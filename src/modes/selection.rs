@@ -32,21 +32,35 @@ impl SelectionApp {
     pub fn new() -> SelectionApp {
         SelectionApp {
             cursor_position: 0,
-            number_of_elements: 13,
+            number_of_elements: 0,
             n_columns: 3,
             n_lines: 3,
-            elements: {
-                // Needs rework obviously
-                let mut temp: Vec<String> = vec![];
-                for i in 0..13 {
-                    temp.push(format!("number : {}", i));
-                }
-                temp
-            },
+            elements: Vec::new(), // Given by the app with set_elements
             current_popup: None,
         }
     }
 
+    //Replaces the elements shown, the cursor stays on the same index if possible
+    pub fn set_elements(&mut self, elements: Vec<String>) {
+        self.number_of_elements = elements.len() as u32;
+        self.elements = elements;
+        self.cursor_position = self
+            .cursor_position
+            .min(self.number_of_elements.saturating_sub(1));
+    }
+
+    pub fn cursor_position(&self) -> u32 {
+        self.cursor_position
+    }
+
+    pub fn set_cursor_position(&mut self, cursor_position: u32) {
+        self.cursor_position = cursor_position.min(self.number_of_elements.saturating_sub(1));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.number_of_elements == 0
+    }
+
     fn mv_left(&mut self) {
         if self.cursor_position > 0 && self.number_of_elements != 0 {
             self.cursor_position = self.cursor_position - 1;
@@ -54,7 +68,7 @@ impl SelectionApp {
     }
 
     fn mv_right(&mut self) {
-        if self.cursor_position < self.number_of_elements - 1 && self.number_of_elements != 0 {
+        if self.number_of_elements != 0 && self.cursor_position < self.number_of_elements - 1 {
             self.cursor_position = self.cursor_position + 1;
         }
     }
@@ -66,7 +80,7 @@ impl SelectionApp {
     }
 
     fn mv_down(&mut self) {
-        if self.cursor_position < self.number_of_elements - self.n_columns {
+        if self.cursor_position + self.n_columns < self.number_of_elements {
             self.cursor_position = self.cursor_position + self.n_columns;
         }
     }
@@ -76,19 +90,69 @@ impl SelectionApp {
         if self.current_popup.is_none() {
             // Check if a popup is active
             match key.code {
-                Char('e') | Enter => {
+                Char('e') | Enter if self.number_of_elements != 0 => {
                     if current_mode == Mode::SelectionDeck {
                         return Message::ChangeMode(Mode::SelectionCard);
                     } else if current_mode == Mode::SelectionCard {
                         return Message::ChangeMode(Mode::Edit);
                     }
                 }
-                Char('t') => {
+                Char('o') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionCard {
+                        return Message::OpenExternalEditor;
+                    }
+                }
+                Char('i') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionCard {
+                        return Message::ChangeMode(Mode::Occlusion);
+                    }
+                }
+                Char('a') => {
+                    if current_mode == Mode::SelectionCard {
+                        return Message::AddCard;
+                    }
+                }
+                Char('s') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::SwitchScheduler;
+                    }
+                }
+                Char('O') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::OptimizeScheduler;
+                    }
+                }
+                Char('v') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::Reschedule;
+                    }
+                }
+                Char('f') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::ShowForecast;
+                    }
+                }
+                Char('R') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::ReplayLog;
+                    }
+                }
+                Char('t') if self.number_of_elements != 0 => {
                     if current_mode == Mode::SelectionDeck {
                         return Message::ChangeMode(Mode::Testing);
                     }
                 }
-                Char('q') | Esc => return Message::ChangeMode(Mode::Quit),
+                Char('c') if self.number_of_elements != 0 => {
+                    if current_mode == Mode::SelectionDeck {
+                        return Message::CustomStudy;
+                    }
+                }
+                Char('q') | Esc => {
+                    if current_mode == Mode::SelectionCard {
+                        return Message::ChangeMode(Mode::SelectionDeck);
+                    }
+                    return Message::ChangeMode(Mode::Quit);
+                }
                 Char('h') | Left => self.mv_left(),
                 Char('l') | Right => self.mv_right(),
                 Char('k') | Up => self.mv_up(),
@@ -115,10 +179,10 @@ impl SelectionApp {
                     let result = self.current_popup.as_mut().unwrap().handle_key_press(key);
                     if result == self.current_popup.as_mut().unwrap().number_of_buttons {
                     } else {
+                        self.current_popup = None;
                         if result == 0 {
                             return Message::Delete(self.cursor_position);
                         }
-                        self.current_popup = None;
                     }
                 }
             }
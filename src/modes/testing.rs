@@ -1,14 +1,1194 @@
-use crate::app::Message;
+// Review session of a deck: the front of a card is shown, the back is revealed
+// on demand and the user grades how well they remembered it. Cards in learning
+// come back in the same session when their step is due. Each card is timed from
+// the moment its back is revealed to its grade.
 
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::answer::{self, DiffPart};
+use crate::app::{Message, Mode};
+use crate::card::{Card, CardKind, ReviewItem};
+use crate::custom_study::{self, CustomStudy};
+use crate::deck::Deck;
+use crate::forecast;
+use crate::formula;
+use crate::leech::LEECH_TAG;
+use crate::multiple_choice::{self, Options};
+use crate::occlusion::{self, ImageView};
+use crate::ordered_list;
+use crate::popup::{self, Popup};
+use crate::review_log::{Review, ScheduleChange};
+use crate::scheduler::{Grade, Schedule, Scheduler, Sm2, Steps};
+use crate::session::{format_duration, SessionSummary, TimeLimits};
+use crate::study_queue;
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::Result;
 use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span, Text},
+    widgets::{Block, Paragraph, Widget, Wrap},
+};
+use ratatui_image::picker::Picker;
+
+// State of the session before a grade, to undo it. The card gets back what
+// the grade and its leech popup change, the rest of an edit from the popup
+// is kept.
+struct Undo {
+    item: ReviewItem,
+    schedule: Schedule, // Of the item before the grade
+    // Of the card before the grade
+    tags: Vec<String>,
+    suspended: bool,
+    logged: bool, // The review could be written in the log
+    queue: VecDeque<ReviewItem>,
+    learning: Vec<(DateTime<Utc>, ReviewItem)>,
+    shown_at: DateTime<Utc>,
+}
+
+enum TestingPopupTypes {
+    Leech(u64), // Id of the card that became a leech
+}
 
-pub struct TestingApp {}
+// When nothing else is left, learning cards due in less than this are shown
+// without waiting
+const LEARN_AHEAD_MINUTES: i64 = 20;
+
+pub struct TestingApp {
+    // Owned during the session, given back to the app by finish
+    deck: Option<Deck>,
+    current: Option<ReviewItem>, // The item shown
+    // Items left to review
+    queue: VecDeque<ReviewItem>,
+    // Items in (re)learning graded in this session, by due time
+    learning: Vec<(DateTime<Utc>, ReviewItem)>,
+    revealed: bool,
+    // Answer of a "type answer" card, compared to the back when revealed
+    typed: String,
+    diff: Vec<DiffPart>,
+    // The typed answer is a formula equivalent to the expected one
+    equivalent: bool,
+    suggested_grade: Option<Grade>,
+    // Options of a multiple choice card, drawn when it is shown
+    options: Options,
+    option_cursor: usize,
+    chosen_option: Option<usize>,
+    // Items of an ordered list card as arranged by the user, by index in the
+    // back, or the number of them revealed one at a time
+    arrangement: Vec<usize>,
+    arrangement_cursor: usize,
+    items_shown: usize,
+    // Image of an image occlusion card with the mask of the item, and without
+    // it once revealed. They are encoded while rendering, hence the RefCell.
+    masked_image: RefCell<ImageView>,
+    unmasked_image: RefCell<ImageView>,
+    image_error: Option<String>,
+    // Reviews of the session, also those not logged in a cram session
+    reviews: Vec<Review>,
+    started_at: DateTime<Utc>,
+    shown_at: DateTime<Utc>, // When the current card was shown
+    revealed_at: DateTime<Utc>,
+    time_limits: TimeLimits,
+    summary: bool,
+    // Grades of the session, the last one first undone
+    undo_stack: Vec<Undo>,
+    // Whether the grades change the schedules, not in a cram session
+    reschedule: bool,
+    current_popup: Option<Popup<TestingPopupTypes>>,
+    // Card opened in the external editor from the leech popup
+    edited_card: Option<u64>,
+    // Those of the deck
+    scheduler: Box<dyn Scheduler>,
+    steps: Steps,
+}
 
 impl TestingApp {
-    pub fn new() -> TestingApp {
-        TestingApp {}
+    pub fn new(picker: Picker) -> TestingApp {
+        TestingApp {
+            deck: None,
+            current: None,
+            queue: VecDeque::new(),
+            learning: Vec::new(),
+            revealed: false,
+            typed: String::new(),
+            diff: Vec::new(),
+            equivalent: false,
+            suggested_grade: None,
+            options: Options::default(),
+            option_cursor: 0,
+            chosen_option: None,
+            arrangement: Vec::new(),
+            arrangement_cursor: 0,
+            items_shown: 0,
+            masked_image: RefCell::new(ImageView::new(picker)),
+            unmasked_image: RefCell::new(ImageView::new(picker)),
+            image_error: None,
+            reviews: Vec::new(),
+            started_at: DateTime::default(),
+            shown_at: DateTime::default(),
+            revealed_at: DateTime::default(),
+            time_limits: TimeLimits::default(),
+            summary: false,
+            undo_stack: Vec::new(),
+            reschedule: true,
+            current_popup: None,
+            edited_card: None,
+            scheduler: Box::new(Sm2::default()),
+            steps: Steps::default(),
+        }
+    }
+
+    pub fn start(&mut self, deck: Deck, now: DateTime<Utc>) {
+        let queue = study_queue::study_queue(&deck, now);
+        self.start_with(deck, queue, true, now);
+    }
+
+    //Custom study session, see custom_study.rs
+    pub fn start_custom(
+        &mut self,
+        deck: Deck,
+        study: &CustomStudy,
+        reschedule: bool,
+        now: DateTime<Utc>,
+    ) {
+        let queue = custom_study::custom_queue(&deck, study, now);
+        self.start_with(deck, queue, reschedule, now);
+    }
+
+    fn start_with(
+        &mut self,
+        deck: Deck,
+        queue: VecDeque<ReviewItem>,
+        reschedule: bool,
+        now: DateTime<Utc>,
+    ) {
+        self.queue = queue;
+        self.reschedule = reschedule;
+        self.learning.clear();
+        self.undo_stack.clear();
+        self.current_popup = None;
+        self.scheduler = deck.scheduler.scheduler();
+        self.steps = deck.steps.clone();
+        self.time_limits = deck.time.clone();
+        self.deck = Some(deck);
+        self.revealed = false;
+        self.reviews.clear();
+        self.started_at = now;
+        self.next_card(now);
+    }
+
+    //Ends the session and gives the deck back
+    pub fn finish(&mut self) -> Option<Deck> {
+        self.queue.clear();
+        self.learning.clear();
+        self.current = None;
+        self.deck.take()
+    }
+
+    //Learning cards whose step is due come first, then the queue, then the
+    //learning cards due soon. The summary is shown when there is none, or
+    //when the time budget of the session is spent.
+    fn next_card(&mut self, now: DateTime<Utc>) {
+        self.shown_at = now;
+        if let Some(budget) = self.time_limits.session_budget() {
+            if now - self.started_at >= budget {
+                self.current = None;
+                self.summary = true;
+                return;
+            }
+        }
+        let learning_due = |limit: DateTime<Utc>, learning: &[(DateTime<Utc>, ReviewItem)]| {
+            learning.first().is_some_and(|(due, _)| *due <= limit)
+        };
+        self.current = if learning_due(now, &self.learning) {
+            Some(self.learning.remove(0).1)
+        } else if let Some(item) = self.queue.pop_front() {
+            Some(item)
+        } else if learning_due(now + Duration::minutes(LEARN_AHEAD_MINUTES), &self.learning) {
+            Some(self.learning.remove(0).1)
+        } else {
+            None
+        };
+        self.summary = self.current.is_none();
+        self.prepare_card();
+    }
+
+    //Draws the options of a multiple choice card, shuffles the items of an
+    //ordered list, loads the image of an image occlusion
+    fn prepare_card(&mut self) {
+        self.option_cursor = 0;
+        self.chosen_option = None;
+        self.arrangement_cursor = 0;
+        self.items_shown = 0;
+        let mut rng = rand::thread_rng();
+        self.options = match (self.deck.as_ref(), self.current_card()) {
+            (Some(deck), Some(card)) if card.kind == CardKind::MultipleChoice => {
+                multiple_choice::options(deck, card, &mut rng)
+            }
+            _ => Options::default(),
+        };
+        self.arrangement = match self.current_card() {
+            Some(card) if card.kind == CardKind::OrderedList => {
+                ordered_list::shuffled_order(ordered_list::items(&card.back).len(), &mut rng)
+            }
+            _ => Vec::new(),
+        };
+        self.prepare_image();
+    }
+
+    fn prepare_image(&mut self) {
+        self.image_error = None;
+        let (Some(deck), Some(card), Some(item)) =
+            (self.deck.as_ref(), self.current_card(), self.current)
+        else {
+            return;
+        };
+        let (masked, unmasked) = match card.kind {
+            CardKind::ImageOcclusion => match occlusion::load_image(&deck.image_path(card)) {
+                Ok(image) => {
+                    let masks: Vec<_> = card
+                        .masks
+                        .get(item.ordinal as usize)
+                        .map(|mask| (*mask, occlusion::ACTIVE_MASK_COLOR))
+                        .into_iter()
+                        .collect();
+                    (Some(occlusion::draw_masks(&image, &masks)), Some(image))
+                }
+                Err(error) => {
+                    self.image_error = Some(format!("{:#}", error));
+                    (None, None)
+                }
+            },
+            _ => (None, None),
+        };
+        self.masked_image.get_mut().set_image(masked);
+        self.unmasked_image.get_mut().set_image(unmasked);
+    }
+
+    //Moves the item under the cursor by one place, the cursor follows it
+    fn move_item(&mut self, down: bool) {
+        let from = self.arrangement_cursor;
+        let to = if down { from + 1 } else { from.wrapping_sub(1) };
+        if to < self.arrangement.len() {
+            self.arrangement.swap(from, to);
+            self.arrangement_cursor = to;
+        }
     }
+
+    fn reveal(&mut self, now: DateTime<Utc>) {
+        self.revealed = true;
+        self.revealed_at = now;
+    }
+
+    fn check_arrangement(&mut self, now: DateTime<Utc>) {
+        let correct = ordered_list::score(&self.arrangement);
+        self.suggested_grade = Some(ordered_list::suggest_grade(correct, self.arrangement.len()));
+        self.reveal(now);
+    }
+
+    //Shows the next item of the list in its place, the card is graded by hand
+    //once they are all shown
+    fn show_next_item(&mut self, now: DateTime<Utc>) {
+        self.items_shown += 1;
+        if self.items_shown >= self.arrangement.len() {
+            self.reveal(now);
+        }
+    }
+
+    //The grade follows from the option, None when the time ran out
+    fn choose(&mut self, option: Option<usize>, now: DateTime<Utc>) {
+        self.chosen_option = option;
+        self.suggested_grade = if option == Some(self.options.correct) {
+            Some(Grade::Good)
+        } else {
+            Some(Grade::Again)
+        };
+        self.reveal(now);
+    }
+
+    fn current_card(&self) -> Option<&Card> {
+        let deck = self.deck.as_ref()?;
+        deck.card(self.current?.card_id)
+    }
+
+    //Reveals the answer once the card was shown for the auto reveal time,
+    //called while no key is pressed
+    pub fn tick(&mut self, now: DateTime<Utc>) {
+        let Some(delay) = self.time_limits.auto_reveal() else {
+            return;
+        };
+        if self.revealed || self.current.is_none() || now - self.shown_at < delay {
+            return;
+        }
+        match self.current_card().map(|card| card.kind) {
+            Some(CardKind::TypeAnswer) => self.submit_answer(now),
+            Some(CardKind::MultipleChoice) => self.choose(None, now),
+            _ => self.reveal(now),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.queue.len() + self.learning.len() + self.current.iter().count()
+    }
+
     pub fn handle_key_press(&mut self, key: KeyEvent) -> Message {
-        todo!()
+        self.handle_key_press_at(key, Utc::now())
+    }
+
+    fn handle_key_press_at(&mut self, key: KeyEvent, now: DateTime<Utc>) -> Message {
+        use KeyCode::*;
+        if self.current_popup.is_some() {
+            return self.handle_popup_key_press(key, now);
+        }
+        let typing = !self.revealed
+            && self
+                .current_card()
+                .is_some_and(|card| card.kind == CardKind::TypeAnswer);
+        let multiple_choice = self
+            .current_card()
+            .is_some_and(|card| card.kind == CardKind::MultipleChoice);
+        let choosing = multiple_choice && !self.revealed;
+        let arranging = !self.revealed
+            && self
+                .current_card()
+                .is_some_and(|card| card.kind == CardKind::OrderedList);
+        if key.code == Char('u') && !typing {
+            return self.undo();
+        }
+        if self.summary {
+            return match key.code {
+                Char('q') | Esc | Enter | Char(' ') => Message::ChangeMode(Mode::SelectionDeck),
+                _ => Message::Nothing,
+            };
+        }
+        match key.code {
+            Char(c) if typing => self.typed.push(c),
+            Backspace if typing => {
+                self.typed.pop();
+            }
+            Enter if typing => self.submit_answer(now),
+            Char(c) if choosing && c.is_ascii_digit() => {
+                let option = (c as usize).wrapping_sub('1' as usize);
+                if option < self.options.texts.len() {
+                    self.choose(Some(option), now);
+                }
+            }
+            Char('h') | Left if choosing => {
+                self.option_cursor = self.option_cursor.saturating_sub(1)
+            }
+            Char('l') | Right if choosing => {
+                self.option_cursor = (self.option_cursor + 1).min(self.options.texts.len() - 1);
+            }
+            Char(' ') | Enter if choosing => self.choose(Some(self.option_cursor), now),
+            Char('j') | Down if arranging && self.items_shown == 0 => {
+                let last = self.arrangement.len().saturating_sub(1);
+                self.arrangement_cursor = (self.arrangement_cursor + 1).min(last);
+            }
+            Char('k') | Up if arranging && self.items_shown == 0 => {
+                self.arrangement_cursor = self.arrangement_cursor.saturating_sub(1);
+            }
+            Char('J') if arranging && self.items_shown == 0 => self.move_item(true),
+            Char('K') if arranging && self.items_shown == 0 => self.move_item(false),
+            Enter if arranging && self.items_shown == 0 => self.check_arrangement(now),
+            Char(' ') if arranging => self.show_next_item(now),
+            Enter if self.revealed && self.suggested_grade.is_some() => {
+                return self.grade(self.suggested_grade.unwrap(), now);
+            }
+            Char(' ') | Enter => self.reveal(now),
+            Char(c) if self.revealed && !multiple_choice => {
+                if let Some(grade) = Grade::from_key(c) {
+                    return self.grade(grade, now);
+                }
+            }
+            Char('q') | Esc => self.summary = true,
+            _ => {}
+        }
+        Message::Nothing
+    }
+
+    fn submit_answer(&mut self, now: DateTime<Utc>) {
+        let (Some(card), Some(item)) = (self.current_card(), self.current) else {
+            return;
+        };
+        let expected = card.answer(item.ordinal);
+        let diff = answer::diff(&self.typed, expected);
+        // Formulas are right when equivalent, "2x+x" for "3x"
+        let equivalent =
+            formula::is_formula(expected) && formula::equivalent(&self.typed, expected);
+        self.suggested_grade = Some(if equivalent {
+            Grade::Good
+        } else {
+            answer::suggest_grade(&diff)
+        });
+        self.diff = diff;
+        self.equivalent = equivalent;
+        self.reveal(now);
+    }
+
+    //Schedules the current card and saves the deck so that no review is lost
+    fn grade(&mut self, grade: Grade, now: DateTime<Utc>) -> Message {
+        let (Some(deck), Some(item)) = (self.deck.as_mut(), self.current) else {
+            return Message::Nothing;
+        };
+        let leech = deck.leech.clone();
+        let Some(card) = deck.card(item.card_id) else {
+            return Message::Nothing;
+        };
+        let previous = card.schedule(item.ordinal);
+        let mut undo = Undo {
+            item,
+            schedule: previous.clone(),
+            tags: card.tags.clone(),
+            suspended: card.suspended,
+            logged: false,
+            queue: self.queue.clone(),
+            learning: self.learning.clone(),
+            shown_at: self.shown_at,
+        };
+        let mut review = Review {
+            item,
+            time: now,
+            grade,
+            time_taken: now - self.revealed_at,
+            change: None,
+        };
+        // The siblings are buried for the rest of the day
+        self.queue.retain(|other| other.card_id != item.card_id);
+        if !self.reschedule {
+            // Nothing is written, not even in the review log (see custom_study.rs),
+            // the forgotten items come back at the end
+            if grade == Grade::Again {
+                self.queue.push_back(item);
+            }
+            self.reviews.push(review);
+            self.undo_stack.push(undo);
+            self.answered();
+            self.next_card(now);
+            return Message::Nothing;
+        }
+        let mut schedule = self
+            .steps
+            .review(self.scheduler.as_ref(), &previous, grade, now);
+        forecast::fuzz(deck, item, &mut schedule, now);
+        let Some(card) = deck.card_mut(item.card_id) else {
+            return Message::Nothing;
+        };
+        review.change = Some(ScheduleChange::new(&previous, &schedule));
+        self.reviews.push(review.clone());
+        let lapses = schedule.lapses;
+        let is_leech = lapses > previous.lapses && leech.is_leech(lapses);
+        if is_leech {
+            leech.mark(card);
+        }
+        if let (true, Some(due), false) = (schedule.is_learning(), schedule.due, card.suspended) {
+            let position = self.learning.partition_point(|(other, _)| *other <= due);
+            self.learning.insert(position, (due, item));
+        }
+        let suspended = card.suspended;
+        card.set_schedule(item.ordinal, schedule);
+        let logged_reviews = deck.review_log.reviews.len();
+        let message = match deck.log_review(review) {
+            Ok(()) => Message::Nothing,
+            Err(error) => Message::SaveFailed(format!("{:#}", error)),
+        };
+        undo.logged = deck.review_log.reviews.len() > logged_reviews;
+        self.undo_stack.push(undo);
+        self.answered();
+        if is_leech {
+            self.show_leech_popup(item.card_id, lapses, suspended);
+        }
+        self.next_card(now);
+        message
+    }
+
+    fn answered(&mut self) {
+        self.revealed = false;
+        self.typed.clear();
+        self.diff.clear();
+        self.equivalent = false;
+        self.suggested_grade = None;
+    }
+
+    fn show_leech_popup(&mut self, card_id: u64, lapses: u32, suspended: bool) {
+        let content = format!(
+            "This card was forgotten {} times, it is tagged \"{}\".{}",
+            lapses,
+            LEECH_TAG,
+            if suspended { " It was suspended." } else { "" }
+        );
+        self.current_popup = Some(Popup::new(
+            TestingPopupTypes::Leech(card_id),
+            content,
+            vec![
+                String::from("EDIT"),
+                String::from("SUSPEND"),
+                String::from("KEEP"),
+            ],
+        ));
+    }
+
+    fn handle_popup_key_press(&mut self, key: KeyEvent, now: DateTime<Utc>) -> Message {
+        let popup = self.current_popup.as_mut().unwrap();
+        let result = popup.handle_key_press(key);
+        if result == popup.number_of_buttons {
+            return Message::Nothing;
+        }
+        let TestingPopupTypes::Leech(card_id) = self.current_popup.take().unwrap().popup_type;
+        match result {
+            0 => {
+                self.edited_card = Some(card_id);
+                Message::OpenExternalEditor
+            }
+            1 => self.set_suspended(card_id, true, now),
+            // A card suspended by the deck settings comes back from the next session
+            _ => self.set_suspended(card_id, false, now),
+        }
+    }
+
+    //Suspending takes the items of the card out of the session
+    fn set_suspended(&mut self, card_id: u64, suspended: bool, now: DateTime<Utc>) -> Message {
+        let Some(deck) = self.deck.as_mut() else {
+            return Message::Nothing;
+        };
+        let Some(card) = deck.card_mut(card_id) else {
+            return Message::Nothing;
+        };
+        if card.suspended == suspended {
+            return Message::Nothing;
+        }
+        card.suspended = suspended;
+        let result = deck.save();
+        if suspended {
+            self.queue.retain(|item| item.card_id != card_id);
+            self.learning.retain(|(_, item)| item.card_id != card_id);
+            if self.current.is_some_and(|item| item.card_id == card_id) {
+                self.revealed = false;
+                self.next_card(now);
+            }
+        }
+        match result {
+            Ok(()) => Message::Nothing,
+            Err(error) => Message::SaveFailed(format!("{:#}", error)),
+        }
+    }
+
+    //Card to open in the external editor, asked for by the leech popup
+    pub fn edited_card(&self) -> Option<&Card> {
+        self.deck.as_ref()?.card(self.edited_card?)
+    }
+
+    //Replaces the edited card by its new version and saves the deck
+    pub fn store_edited_card(&mut self, card: Card) -> Result<()> {
+        self.edited_card = None;
+        let Some(deck) = self.deck.as_mut() else {
+            return Ok(());
+        };
+        let current = self.current.is_some_and(|item| item.card_id == card.id);
+        if let Some(stored) = deck.card_mut(card.id) {
+            *stored = card;
+        }
+        let result = deck.save();
+        // Its choices, list or image can have changed
+        if current {
+            self.prepare_card();
+        }
+        result
+    }
+
+    //Puts the last graded item back in front with its previous schedule, and
+    //removes its review from the log
+    fn undo(&mut self) -> Message {
+        let (Some(deck), Some(undo)) = (self.deck.as_mut(), self.undo_stack.pop()) else {
+            return Message::Nothing;
+        };
+        if let Some(card) = deck.card_mut(undo.item.card_id) {
+            card.tags = undo.tags;
+            card.suspended = undo.suspended;
+        }
+        let result = if undo.logged {
+            deck.undo_review(undo.schedule)
+        } else if self.reschedule {
+            if let Some(card) = deck.card_mut(undo.item.card_id) {
+                card.set_schedule(undo.item.ordinal, undo.schedule);
+            }
+            deck.save()
+        } else {
+            Ok(())
+        };
+        self.queue = undo.queue;
+        self.learning = undo.learning;
+        self.current = Some(undo.item);
+        self.prepare_card();
+        self.shown_at = undo.shown_at;
+        self.reviews.pop();
+        self.revealed = false;
+        self.typed.clear();
+        self.diff.clear();
+        self.equivalent = false;
+        self.suggested_grade = None;
+        self.summary = false;
+        match result {
+            Ok(()) => Message::Nothing,
+            Err(error) => Message::SaveFailed(format!("{:#}", error)),
+        }
+    }
+
+    fn render_card(&self, deck: &Deck, area: Rect, buf: &mut Buffer) {
+        let Some(item) = self.current else {
+            return;
+        };
+        let Some(card) = deck.card(item.card_id) else {
+            return;
+        };
+        if card.kind == CardKind::ImageOcclusion {
+            self.render_image_occlusion(card, area, buf);
+            return;
+        }
+        let [front_area, back_area, controls_area] = Layout::vertical([
+            Constraint::Percentage(50),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let front = if self.revealed {
+            card.revealed_question(item.ordinal)
+        } else {
+            card.question(item.ordinal)
+        };
+        Paragraph::new(front)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Question"))
+            .render(front_area, buf);
+        let controls = if card.kind == CardKind::MultipleChoice {
+            self.render_options(back_area, buf);
+            match (self.revealed, self.suggested_grade) {
+                (true, Some(grade)) => format!("[Enter] {}", grade.name()),
+                _ => String::from("[1-9] Choose  [h/l] Move  [Enter] Choose  [q] End"),
+            }
+        } else if self.revealed {
+            let mut back = if card.kind == CardKind::OrderedList {
+                self.ordered_list_text(card)
+            } else {
+                Text::from(card.answer(item.ordinal))
+            };
+            if card.kind == CardKind::TypeAnswer {
+                let checked = if self.equivalent {
+                    Line::from(vec![
+                        Span::from(self.typed.clone()).green(),
+                        Span::from("  equivalent to the answer").fg(Color::DarkGray),
+                    ])
+                } else {
+                    diff_line(&self.diff)
+                };
+                back.lines.insert(0, checked);
+                back.lines.insert(1, Line::from(""));
+            }
+            Paragraph::new(back)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title("Answer"))
+                .render(back_area, buf);
+            let mut controls = Grade::ALL
+                .iter()
+                .enumerate()
+                .map(|(index, grade)| format!("[{}] {}", index + 1, grade.name()))
+                .collect::<Vec<String>>()
+                .join("  ");
+            if let Some(grade) = self.suggested_grade {
+                controls.push_str(&format!("  [Enter] {} (suggested)", grade.name()));
+            }
+            controls
+        } else if card.kind == CardKind::OrderedList {
+            let title = if self.items_shown == 0 {
+                "Put in order"
+            } else {
+                "Answer"
+            };
+            Paragraph::new(self.ordered_list_text(card))
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(title))
+                .render(back_area, buf);
+            if self.items_shown == 0 {
+                String::from("[j/k] Select  [J/K] Move  [Enter] Check  [space] Reveal one  [q] End")
+            } else {
+                String::from("[space] Reveal the next  [q] End")
+            }
+        } else if card.kind == CardKind::TypeAnswer {
+            Paragraph::new(Line::from(vec![
+                Span::from(self.typed.as_str()),
+                Span::from(" ").reversed(),
+            ]))
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Your answer"))
+            .render(back_area, buf);
+            String::from("[Enter] Check  [Esc] End")
+        } else {
+            String::from("[space] Reveal  [u] Undo  [q] End")
+        };
+        Line::from(controls).centered().render(controls_area, buf);
+    }
+
+    //The front and, once revealed, the back above the image, the mask is
+    //removed on reveal
+    fn render_image_occlusion(&self, card: &Card, area: Rect, buf: &mut Buffer) {
+        let [text_area, image_area, controls_area] = Layout::vertical([
+            Constraint::Length(4),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let mut text = Text::from(card.front.as_str());
+        if self.revealed && !card.back.is_empty() {
+            text.lines.extend(Text::from(card.back.as_str()).lines);
+        }
+        Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title("Question"))
+            .render(text_area, buf);
+        let block = Block::bordered().title(if self.revealed { "Answer" } else { "Image" });
+        let inner = block.inner(image_area);
+        block.render(image_area, buf);
+        if let Some(error) = self.image_error.as_deref() {
+            Paragraph::new(format!("The image could not be loaded :\n{}", error))
+                .wrap(Wrap { trim: false })
+                .render(inner, buf);
+        } else if self.revealed {
+            self.unmasked_image.borrow_mut().render(inner, buf);
+        } else {
+            self.masked_image.borrow_mut().render(inner, buf);
+        }
+        let controls = if self.revealed {
+            Grade::ALL
+                .iter()
+                .enumerate()
+                .map(|(index, grade)| format!("[{}] {}", index + 1, grade.name()))
+                .collect::<Vec<String>>()
+                .join("  ")
+        } else {
+            String::from("[space] Reveal  [u] Undo  [q] End")
+        };
+        Line::from(controls).centered().render(controls_area, buf);
+    }
+
+    //Items of an ordered list: as arranged, the selected one reversed; in
+    //order as far as revealed one at a time; or, once checked, as arranged
+    //with the right ones green and the wrong ones red followed by the expected
+    //item
+    fn ordered_list_text(&self, card: &Card) -> Text<'static> {
+        let items = ordered_list::items(&card.back);
+        let checked = self.revealed && self.items_shown == 0 && self.suggested_grade.is_some();
+        if self.items_shown > 0 || (self.revealed && !checked) {
+            let shown = if self.revealed {
+                items.len()
+            } else {
+                self.items_shown
+            };
+            return items
+                .iter()
+                .take(shown)
+                .enumerate()
+                .map(|(place, item)| Line::from(format!("{}. {}", place + 1, item)))
+                .collect();
+        }
+        self.arrangement
+            .iter()
+            .enumerate()
+            .map(|(place, index)| {
+                let text = format!("{}. {}", place + 1, items[*index]);
+                if !checked {
+                    if place == self.arrangement_cursor {
+                        Line::from(text).reversed()
+                    } else {
+                        Line::from(text)
+                    }
+                } else if place == *index {
+                    Line::from(text).green()
+                } else {
+                    Line::from(vec![
+                        Span::from(text).red(),
+                        Span::from(format!("  ({})", items[place])).fg(Color::DarkGray),
+                    ])
+                }
+            })
+            .collect()
+    }
+
+    //Once revealed, the answer is green and a wrong choice red
+    fn render_options(&self, area: Rect, buf: &mut Buffer) {
+        let texts: Vec<String> = self
+            .options
+            .texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| format!("{}. {}", index + 1, text))
+            .collect();
+        let buttons: Vec<(&str, Style)> = texts
+            .iter()
+            .enumerate()
+            .map(|(index, text)| {
+                let style = if !self.revealed {
+                    if index == self.option_cursor {
+                        Style::new().red()
+                    } else {
+                        Style::new()
+                    }
+                } else if index == self.options.correct {
+                    Style::new().green()
+                } else if Some(index) == self.chosen_option {
+                    Style::new().red()
+                } else {
+                    Style::new().fg(Color::DarkGray)
+                };
+                (text.as_str(), style)
+            })
+            .collect();
+        popup::render_button_row(&buttons, area, buf);
+    }
+
+    fn render_summary(&self, deck: &Deck, area: Rect, buf: &mut Buffer) {
+        let summary = SessionSummary::new(&self.reviews);
+        let optional =
+            |duration: Option<Duration>| duration.map_or(String::from("-"), format_duration);
+        let mut lines = vec![
+            Line::from(format!(
+                "{} cards reviewed in {}",
+                summary.reviews,
+                format_duration(summary.total_time)
+            ))
+            .bold(),
+            Line::from(format!(
+                "Accuracy {}  Average time {}",
+                summary
+                    .accuracy()
+                    .map_or(String::from("-"), |accuracy| format!(
+                        "{:.0}%",
+                        accuracy * 100.0
+                    )),
+                optional(summary.average_time())
+            )),
+            Line::from(""),
+        ];
+        for grade in Grade::ALL {
+            lines.push(Line::from(format!(
+                "{:>5} : {}  ({} each)",
+                grade.name(),
+                summary.grades[grade as usize].0,
+                optional(summary.average_grade_time(grade))
+            )));
+        }
+        let lapsed = most_lapsed(deck, &self.reviews);
+        if !lapsed.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(Line::from("Most lapsed cards").bold());
+        }
+        for (lapses, card) in lapsed {
+            let front = card.front.lines().next().unwrap_or("");
+            lines.push(Line::from(format!("{} lapses : {}", lapses, front)));
+        }
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from("[Enter] Back to the decks  [u] Undo")
+                .style(Style::new().fg(Color::DarkGray)),
+        );
+        Paragraph::new(lines).centered().render(area, buf);
+    }
+}
+
+//Cards of the reviews forgotten the most often, with their lapses
+fn most_lapsed<'a>(deck: &'a Deck, reviews: &[Review]) -> Vec<(u32, &'a Card)> {
+    const SHOWN: usize = 3;
+    let mut cards: Vec<(u32, &Card)> = deck
+        .cards
+        .iter()
+        .filter(|card| reviews.iter().any(|review| review.item.card_id == card.id))
+        .map(|card| {
+            let lapses = card.schedules.iter().map(|schedule| schedule.lapses).sum();
+            (lapses, card)
+        })
+        .filter(|(lapses, _)| *lapses > 0)
+        .collect();
+    cards.sort_by_key(|(lapses, _)| std::cmp::Reverse(*lapses));
+    cards.truncate(SHOWN);
+    cards
+}
+
+//Typed answer compared to the expected one: correct characters in green,
+//missing ones in red and extra ones crossed out in yellow
+fn diff_line(diff: &[DiffPart]) -> Line<'static> {
+    let spans: Vec<Span> = diff
+        .iter()
+        .map(|part| match part {
+            DiffPart::Correct(c) => Span::from(c.to_string()).green(),
+            DiffPart::Missing(c) => Span::from(c.to_string()).on_red(),
+            DiffPart::Extra(c) => Span::from(c.to_string()).yellow().crossed_out(),
+        })
+        .collect();
+    Line::from(spans)
+}
+
+impl Widget for &TestingApp {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let Some(deck) = self.deck.as_ref() else {
+            return;
+        };
+        let mut title = format!("{} ({} left", deck.name, self.remaining());
+        let elapsed = format_duration(Utc::now() - self.started_at);
+        match self.time_limits.session_budget() {
+            Some(budget) => {
+                title.push_str(&format!(", {} / {})", elapsed, format_duration(budget)))
+            }
+            None => title.push_str(&format!(", {})", elapsed)),
+        }
+        if !self.reschedule {
+            title.push_str(" - cram, the schedules are not changed");
+        }
+        let block = Block::bordered().title(title);
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if self.summary {
+            self.render_summary(deck, inner, buf);
+        } else {
+            self.render_card(deck, inner, buf);
+        }
+        if let Some(popup) = self.current_popup.as_ref() {
+            popup.render(
+                Popup::<TestingPopupTypes>::make_centered_rectangle_area(50, 40, area),
+                buf,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::scheduler::CardState;
+    use crossterm::event::KeyModifiers;
+    use std::fs;
+    use std::path::Path;
+
+    fn press(testing: &mut TestingApp, c: char, now: DateTime<Utc>) -> Message {
+        testing.handle_key_press_at(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE), now)
+    }
+
+    fn answers(testing: &TestingApp) -> [usize; 4] {
+        SessionSummary::new(&testing.reviews)
+            .grades
+            .map(|(count, _)| count)
+    }
+
+    #[test]
+    fn learning_cards_come_back() {
+        let decks_path =
+            std::env::temp_dir().join(format!("balatui-testing-{}", std::process::id()));
+        let mut deck = Deck::new(String::from("test"), &decks_path);
+        deck.add_card(Card::new(String::from("a"), String::from("1")));
+        deck.add_card(Card::new(String::from("b"), String::from("2")));
+        let start = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        testing.start(deck, start);
+        // Grades are ignored before the back is revealed
+        press(&mut testing, '3', start);
+        assert_eq!(testing.current.unwrap().card_id, 1);
+        let answer = |testing: &mut TestingApp, grade: char, minutes: i64| {
+            let now = start + Duration::minutes(minutes);
+            press(testing, ' ', now);
+            press(testing, grade, now);
+            testing.current.map(|item| item.card_id)
+        };
+        assert_eq!(answer(&mut testing, '1', 0), Some(2));
+        // The mistaken Again is undone, the card is shown again as new
+        press(&mut testing, 'u', start);
+        assert_eq!(testing.current.unwrap().card_id, 1);
+        assert!(testing.learning.is_empty());
+        assert!(testing.deck.as_ref().unwrap().cards[0].schedule(0).is_new());
+        assert_eq!(answer(&mut testing, '1', 0), Some(2));
+        // Nothing else to review, the card due in a minute is shown right away
+        assert_eq!(answer(&mut testing, '3', 0), Some(1));
+        assert_eq!(answer(&mut testing, '3', 1), Some(2));
+        assert_eq!(answer(&mut testing, '3', 10), Some(1));
+        assert_eq!(answer(&mut testing, '4', 10), None);
+        assert!(testing.summary);
+        assert_eq!(answers(&testing), [1, 0, 3, 1]);
+        assert!(press(&mut testing, 'q', start) == Message::ChangeMode(Mode::SelectionDeck));
+        let deck = testing.finish().unwrap();
+        fs::remove_dir_all(&decks_path).unwrap();
+        assert!(deck
+            .cards
+            .iter()
+            .all(|card| card.schedule(0).state == CardState::Review));
+        assert!((deck.cards[0].schedule(0).ease - 2.6).abs() < 1e-9);
+        assert_eq!(deck.review_log.reviews.len(), 5);
+    }
+
+    #[test]
+    fn undo_keeps_the_edit_of_a_leech() {
+        let decks_path = std::env::temp_dir().join(format!("balatui-leech-{}", std::process::id()));
+        let mut deck = Deck::new(String::from("test"), &decks_path);
+        let now = Utc::now();
+        let mut card = Card::new(String::from("a"), String::from("1"));
+        card.set_schedule(
+            0,
+            Schedule {
+                state: CardState::Review,
+                due: Some(now),
+                interval: 10.0,
+                repetitions: 3,
+                lapses: 7,
+                ..Schedule::default()
+            },
+        );
+        deck.add_card(card);
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        testing.start(deck, now);
+        press(&mut testing, ' ', now);
+        press(&mut testing, '1', now);
+        // The EDIT button of the leech popup
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        assert!(testing.handle_key_press_at(enter, now) == Message::OpenExternalEditor);
+        let mut edited = testing.edited_card().unwrap().clone();
+        edited.back = String::from("one");
+        testing.store_edited_card(edited).unwrap();
+        press(&mut testing, 'u', now);
+        // Suspended from the popup, the card comes back with the grade
+        press(&mut testing, ' ', now);
+        press(&mut testing, '1', now);
+        press(&mut testing, 'l', now);
+        testing.handle_key_press_at(enter, now);
+        assert!(testing.current.is_none());
+        press(&mut testing, 'u', now);
+        assert_eq!(testing.current.unwrap().card_id, 1);
+        let deck = testing.finish().unwrap();
+        fs::remove_dir_all(&decks_path).unwrap();
+        let card = &deck.cards[0];
+        assert_eq!(card.back, "one");
+        assert!(card.tags.is_empty() && !card.suspended);
+        assert_eq!(card.schedule(0).lapses, 7);
+    }
+
+    #[test]
+    fn typed_answer_suggests_grade() {
+        let decks_path = std::env::temp_dir().join(format!("balatui-typed-{}", std::process::id()));
+        let mut deck = Deck::new(String::from("test"), &decks_path);
+        let mut card = Card::new(String::from("Capital of Italy"), String::from("Rome"));
+        card.kind = CardKind::TypeAnswer;
+        deck.add_card(card);
+        let now = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        testing.start(deck, now);
+        for c in "Rom1e".chars() {
+            press(&mut testing, c, now);
+        }
+        let backspace = KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        testing.handle_key_press_at(backspace, now);
+        testing.handle_key_press_at(backspace, now);
+        press(&mut testing, 'e', now);
+        testing.handle_key_press_at(enter, now);
+        assert!(testing.revealed);
+        assert_eq!(testing.suggested_grade, Some(Grade::Good));
+        testing.handle_key_press_at(enter, now);
+        assert_eq!(answers(&testing), [0, 0, 1, 0]);
+        testing.finish();
+        fs::remove_dir_all(&decks_path).unwrap();
+    }
+
+    #[test]
+    fn cram_keeps_the_schedules() {
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        let mut card = Card::new(String::from("a"), String::from("1"));
+        card.add_tag("exam");
+        deck.add_card(card);
+        let now = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        let study = CustomStudy::Tag(String::from("exam"));
+        testing.start_custom(deck, &study, false, now);
+        press(&mut testing, ' ', now);
+        press(&mut testing, '1', now);
+        // Forgotten, the card comes back right away
+        assert_eq!(testing.current.unwrap().card_id, 1);
+        press(&mut testing, ' ', now);
+        press(&mut testing, '3', now);
+        assert!(testing.summary);
+        let deck = testing.finish().unwrap();
+        assert!(deck.cards[0].schedule(0).is_new());
+        assert!(deck.review_log.reviews.is_empty());
+    }
+
+    #[test]
+    fn auto_reveal_and_time_budget() {
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        for front in ["a", "b", "c"] {
+            deck.add_card(Card::new(String::from(front), String::from("1")));
+        }
+        deck.time.auto_reveal_seconds = 5;
+        deck.time.session_minutes = 1;
+        let start = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        let study = CustomStudy::AheadOfSchedule;
+        testing.start_custom(deck, &study, false, start);
+        testing.tick(start + Duration::seconds(4));
+        assert!(!testing.revealed);
+        testing.tick(start + Duration::seconds(5));
+        assert!(testing.revealed);
+        press(&mut testing, '3', start + Duration::seconds(6));
+        assert_eq!(testing.reviews[0].time_taken, Duration::seconds(1));
+        // The budget is spent, the last card is not shown
+        press(&mut testing, ' ', start + Duration::seconds(61));
+        press(&mut testing, '3', start + Duration::seconds(61));
+        assert!(testing.summary);
+        assert_eq!(testing.queue.len(), 1);
+    }
+
+    #[test]
+    fn multiple_choice_grades_itself() {
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        let mut card = Card::new(String::from("Capital of France"), String::from("Paris"));
+        card.kind = CardKind::MultipleChoice;
+        card.choices = vec![String::from("Lyon")];
+        deck.add_card(card);
+        let now = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        testing.start_custom(deck, &CustomStudy::AheadOfSchedule, false, now);
+        assert_eq!(testing.options.texts.len(), 2);
+        let wrong = 1 - testing.options.correct;
+        press(&mut testing, char::from(b'1' + wrong as u8), now);
+        assert!(testing.revealed);
+        // The grade keys can't change the grade
+        press(&mut testing, '4', now);
+        assert!(testing.revealed);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        testing.handle_key_press_at(enter, now);
+        assert_eq!(testing.reviews[0].grade, Grade::Again);
+    }
+
+    #[test]
+    fn ordered_list_is_scored_by_place() {
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        let mut card = Card::new(String::from("Steps"), String::from("1. a\n2. b\n3. c"));
+        card.kind = CardKind::OrderedList;
+        deck.add_card(card);
+        let now = Utc::now();
+        let mut testing = TestingApp::new(Picker::from_fontsize((8, 16)));
+        testing.start_custom(deck, &CustomStudy::AheadOfSchedule, false, now);
+        // Moved by hand to the order c, a, b: no item at its place
+        testing.arrangement = vec![0, 1, 2];
+        press(&mut testing, 'j', now);
+        press(&mut testing, 'J', now);
+        press(&mut testing, 'k', now);
+        press(&mut testing, 'K', now);
+        assert_eq!(testing.arrangement, vec![2, 0, 1]);
+        let enter = KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE);
+        testing.handle_key_press_at(enter, now);
+        assert_eq!(testing.suggested_grade, Some(Grade::Again));
+        // Edited while shown, the list is shuffled again
+        let mut edited = testing.current_card().unwrap().clone();
+        edited.back = String::from("1. a\n2. b");
+        let _ = testing.store_edited_card(edited); // The deck has no folder to be saved in
+        assert_eq!(testing.arrangement.len(), 2);
     }
 }
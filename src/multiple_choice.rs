@@ -0,0 +1,87 @@
+// Multiple choice cards: the back is shown among wrong answers, the
+// distractors. They are the choices written on the card, or else the backs of
+// the other cards sharing a tag with it.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::card::Card;
+use crate::deck::Deck;
+use crate::leech::LEECH_TAG;
+
+// Distractors shown with the answer
+const DISTRACTORS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Options {
+    pub texts: Vec<String>,
+    pub correct: usize, // Index of the back in texts
+}
+
+//Wrong answers the distractors are drawn from
+pub fn distractor_pool(deck: &Deck, card: &Card) -> Vec<String> {
+    if !card.choices.is_empty() {
+        return card.choices.clone();
+    }
+    // Every leech shares this tag, it says nothing about the content
+    let shares_tag = |other: &Card| {
+        other
+            .tags
+            .iter()
+            .any(|tag| tag != LEECH_TAG && card.has_tag(tag))
+    };
+    let mut pool: Vec<String> = deck
+        .cards
+        .iter()
+        .filter(|other| other.id != card.id && shares_tag(other))
+        .map(|other| other.back.trim().to_string())
+        .filter(|back| !back.is_empty() && back != card.back.trim())
+        .collect();
+    pool.sort_unstable();
+    pool.dedup();
+    pool
+}
+
+pub fn options(deck: &Deck, card: &Card, rng: &mut impl Rng) -> Options {
+    let pool = distractor_pool(deck, card);
+    let mut texts: Vec<String> = pool.choose_multiple(rng, DISTRACTORS).cloned().collect();
+    let correct = rng.gen_range(0..=texts.len());
+    texts.insert(correct, card.back.clone());
+    Options { texts, correct }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::path::Path;
+
+    #[test]
+    fn distractors() {
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        for (front, back, tag) in [
+            ("Capital of France", "Paris", "capitals"),
+            ("Capital of Italy", "Rome", "capitals"),
+            ("Capital of Spain", "Madrid", "capitals"),
+            ("Capital of Peru", "Paris", "capitals"),
+            ("Largest planet", "Jupiter", LEECH_TAG),
+        ] {
+            let mut card = Card::new(String::from(front), String::from(back));
+            card.add_tag(tag);
+            card.add_tag(LEECH_TAG);
+            deck.add_card(card);
+        }
+        let france = deck.cards[0].clone();
+        assert_eq!(
+            distractor_pool(&deck, &france),
+            vec![String::from("Madrid"), String::from("Rome")]
+        );
+        let options = options(&deck, &france, &mut StdRng::seed_from_u64(7));
+        assert_eq!(options.texts.len(), 3);
+        assert_eq!(options.texts[options.correct], "Paris");
+        let mut written = france.clone();
+        written.choices = vec![String::from("Lyon")];
+        assert_eq!(distractor_pool(&deck, &written), vec![String::from("Lyon")]);
+    }
+}
@@ -0,0 +1,215 @@
+// Image occlusion cards: rectangles of an image, the masks, hide what is to be
+// recalled. Each mask is an item of the card, its ordinal is the index of the
+// mask. The images are shown with ratatui-image.
+
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+use ratatui_image::{picker::Picker, protocol::Protocol, Image, Resize};
+use serde::{Deserialize, Serialize};
+
+// Color of the mask reviewed, and of the other masks in the editor
+pub const ACTIVE_MASK_COLOR: Rgba<u8> = Rgba([255, 140, 0, 255]);
+pub const MASK_COLOR: Rgba<u8> = Rgba([90, 90, 90, 255]);
+
+// Fraction of the image a mask is moved or resized by at each key press
+const STEPS: u32 = 50;
+
+// In pixels of the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Mask {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// An image encoded for the terminal, kept between the frames
+pub struct ImageView {
+    picker: Picker,
+    image: Option<DynamicImage>,
+    encoded: Option<(Rect, Protocol)>,
+}
+
+impl Mask {
+    //A quarter of the image, in its center
+    pub fn centered(image_width: u32, image_height: u32) -> Mask {
+        Mask {
+            x: image_width * 3 / 8,
+            y: image_height * 3 / 8,
+            width: (image_width / 4).max(1),
+            height: (image_height / 4).max(1),
+        }
+    }
+
+    //Moves the mask by steps of the image size, it stays inside the image
+    pub fn moved(self, dx: i32, dy: i32, image_width: u32, image_height: u32) -> Mask {
+        let x = step(self.x, dx, image_width).min(image_width.saturating_sub(self.width));
+        let y = step(self.y, dy, image_height).min(image_height.saturating_sub(self.height));
+        Mask { x, y, ..self }
+    }
+
+    //Grows or shrinks the right and bottom sides by steps of the image size
+    pub fn resized(self, dx: i32, dy: i32, image_width: u32, image_height: u32) -> Mask {
+        let width = step(self.width, dx, image_width).min(image_width.saturating_sub(self.x));
+        let height = step(self.height, dy, image_height).min(image_height.saturating_sub(self.y));
+        Mask {
+            width: width.max(1),
+            height: height.max(1),
+            ..self
+        }
+    }
+
+    //Moved and shrunk into the image, which can have been replaced by a
+    //smaller one since the mask was drawn
+    pub fn clamped(self, image_width: u32, image_height: u32) -> Mask {
+        let x = self.x.min(image_width.saturating_sub(1));
+        let y = self.y.min(image_height.saturating_sub(1));
+        Mask {
+            x,
+            y,
+            width: self.width.min(image_width.saturating_sub(x)).max(1),
+            height: self.height.min(image_height.saturating_sub(y)).max(1),
+        }
+    }
+}
+
+fn step(value: u32, steps: i32, size: u32) -> u32 {
+    let length = (size / STEPS).max(1) as i64;
+    (value as i64 + steps as i64 * length).max(0) as u32
+}
+
+pub fn load_image(path: &Path) -> Result<DynamicImage> {
+    image::open(path).wrap_err_with(|| format!("open {}", path.display()))
+}
+
+//Copy of the image with the masks filled with their color
+pub fn draw_masks(image: &DynamicImage, masks: &[(Mask, Rgba<u8>)]) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let mut drawn: RgbaImage = image.to_rgba8();
+    for (mask, color) in masks {
+        for y in mask.y.min(height)..(mask.y + mask.height).min(height) {
+            for x in mask.x.min(width)..(mask.x + mask.width).min(width) {
+                drawn.put_pixel(x, y, *color);
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(drawn)
+}
+
+//Guesses the graphics protocol of the terminal, must be called before the
+//events are read. Half blocks are used when the terminal can't be queried.
+pub fn terminal_picker() -> Picker {
+    Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 16)))
+}
+
+impl ImageView {
+    pub fn new(picker: Picker) -> ImageView {
+        ImageView {
+            picker,
+            image: None,
+            encoded: None,
+        }
+    }
+
+    pub fn set_image(&mut self, image: Option<DynamicImage>) {
+        self.image = image;
+        self.encoded = None;
+    }
+
+    //The image is encoded again only when it or the area changed
+    pub fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        let Some(image) = self.image.as_ref() else {
+            return;
+        };
+        if self.encoded.as_ref().map(|(rect, _)| *rect) != Some(area) {
+            let protocol = self
+                .picker
+                .new_protocol(image.clone(), area, Resize::Fit(None));
+            self.encoded = protocol.ok().map(|protocol| (area, protocol));
+        }
+        if let Some((_, protocol)) = self.encoded.as_mut() {
+            Image::new(protocol).render(area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Color;
+
+    #[test]
+    fn masks_stay_in_the_image() {
+        let mask = Mask::centered(100, 40);
+        assert_eq!(
+            mask,
+            Mask {
+                x: 37,
+                y: 15,
+                width: 25,
+                height: 10
+            }
+        );
+        assert_eq!(mask.moved(-100, 0, 100, 40).x, 0);
+        assert_eq!(
+            mask.moved(100, 100, 100, 40),
+            Mask {
+                x: 75,
+                y: 30,
+                ..mask
+            }
+        );
+        assert_eq!(
+            mask.resized(100, -100, 100, 40),
+            Mask {
+                width: 63,
+                height: 1,
+                ..mask
+            }
+        );
+        // Drawn on a larger image
+        let outside = Mask {
+            x: 120,
+            y: 10,
+            width: 30,
+            height: 40,
+        };
+        assert_eq!(outside.resized(1, 1, 100, 40).width, 1);
+        assert_eq!(
+            outside.clamped(100, 40),
+            Mask {
+                x: 99,
+                y: 10,
+                width: 1,
+                height: 30
+            }
+        );
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        let mask = Mask {
+            x: 2,
+            y: 3,
+            width: 5,
+            height: 5,
+        };
+        let drawn = draw_masks(&image, &[(mask, MASK_COLOR)]).to_rgba8();
+        assert_eq!(*drawn.get_pixel(3, 3), MASK_COLOR);
+        assert_eq!(*drawn.get_pixel(1, 3), Rgba([0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn image_is_rendered_in_half_blocks() {
+        let mut image = RgbaImage::new(16, 32);
+        for pixel in image.pixels_mut() {
+            *pixel = Rgba([255, 0, 0, 255]);
+        }
+        let mut view = ImageView::new(Picker::from_fontsize((8, 16)));
+        view.set_image(Some(DynamicImage::ImageRgba8(image)));
+        let area = Rect::new(0, 0, 4, 4);
+        let mut buf = Buffer::empty(area);
+        view.render(area, &mut buf);
+        assert_eq!(buf.cell((0, 0)).unwrap().fg, Color::Rgb(255, 0, 0));
+    }
+}
@@ -0,0 +1,99 @@
+// Ordered list cards: the back is a list, one item per line, to recall in
+// order, like the steps of a proof. The items are shown shuffled and put back
+// in order by the user, the grade follows from the items at their place.
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::scheduler::Grade;
+
+//Items of the back, without their "1." or "-" list markers
+pub fn items(back: &str) -> Vec<&str> {
+    back.lines()
+        .map(strip_marker)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn strip_marker(line: &str) -> &str {
+    let line = line.trim();
+    for bullet in ["- ", "* "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            return item.trim_start();
+        }
+    }
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 {
+        for separator in [". ", ") "] {
+            if let Some(item) = line[digits..].strip_prefix(separator) {
+                return item.trim_start();
+            }
+        }
+    }
+    line
+}
+
+//Indices of the items in a shuffled order, never the right one when there
+//are several items
+pub fn shuffled_order(count: usize, rng: &mut impl Rng) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..count).collect();
+    if count < 2 {
+        return order;
+    }
+    while order
+        .iter()
+        .enumerate()
+        .all(|(place, index)| place == *index)
+    {
+        order.shuffle(rng);
+    }
+    order
+}
+
+//Number of items at their place
+pub fn score(order: &[usize]) -> usize {
+    order
+        .iter()
+        .enumerate()
+        .filter(|(place, index)| place == *index)
+        .count()
+}
+
+//Good when every item is at its place, Hard for at least half of them
+pub fn suggest_grade(correct: usize, count: usize) -> Grade {
+    if correct == count {
+        Grade::Good
+    } else if correct * 2 >= count {
+        Grade::Hard
+    } else {
+        Grade::Again
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn items_and_score() {
+        let back = "1. Assume $\\sqrt{2} = p/q$\n2) Square it\n\n- $p$ is even\n* So is $q$\n2024 is a year";
+        assert_eq!(
+            items(back),
+            vec![
+                "Assume $\\sqrt{2} = p/q$",
+                "Square it",
+                "$p$ is even",
+                "So is $q$",
+                "2024 is a year"
+            ]
+        );
+        let order = shuffled_order(5, &mut StdRng::seed_from_u64(7));
+        assert!(score(&order) < 5);
+        assert_eq!(score(&[0, 2, 1, 3]), 2);
+        assert_eq!(suggest_grade(2, 4), Grade::Hard);
+        assert_eq!(suggest_grade(1, 4), Grade::Again);
+        assert_eq!(suggest_grade(4, 4), Grade::Good);
+    }
+}
@@ -6,7 +6,7 @@ use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
-    widgets::{Block, Paragraph, Widget},
+    widgets::{Block, Paragraph, Widget, Wrap},
 };
 
 pub struct Popup<T> {
@@ -41,6 +41,11 @@ impl<T> Popup<T> {
             popup_type,
         }
     }
+    //Rows needed to show the whole content above the buttons
+    pub fn height(&self) -> u16 {
+        self.popup_content.lines().count() as u16 + 5
+    }
+
     fn mv_left(&mut self) {
         if self.cursor_position > 0 && self.number_of_buttons != 0 {
             self.cursor_position = self.cursor_position - 1;
@@ -53,10 +58,12 @@ impl<T> Popup<T> {
         }
     }
 
+    //Esc chooses the last button, the one leaving the popup as it is
     pub fn handle_key_press(&mut self, key: KeyEvent) -> u16 {
         use KeyCode::*;
         match key.code {
             Char('e') | Enter => return self.cursor_position,
+            Esc if self.number_of_buttons > 0 => return self.number_of_buttons - 1,
             Char('h') | Left => self.mv_left(),
             Char('l') | Right => self.mv_right(),
             Char('k') | Up => self.mv_right(),
@@ -73,33 +80,48 @@ impl<T> Widget for &Popup<T> {
 
         let vertical_layout = Layout::default() //Layout for a big paragrapg on top of the buttons
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
-            .split(popup_block.inner(area));
-
-        let horizontal_layout = Layout::default() //Layout on the bottom part for the different buttons
-            .direction(Direction::Horizontal)
             .constraints(vec![
-                Constraint::Percentage(
-                    (100 / self.number_of_buttons) as u16
-                );
-                self.number_of_buttons as usize
+                Constraint::Min(self.popup_content.lines().count() as u16),
+                Constraint::Percentage(50),
             ])
-            .split(vertical_layout[1]);
+            .split(popup_block.inner(area));
 
         let popup_content = Paragraph::new(self.popup_content.as_str());
         popup_block.render(area, buf);
         popup_content.render(vertical_layout[0], buf);
 
-        for n in 0..self.number_of_buttons {
-            let button;
-            if n == self.cursor_position {
-                button = Paragraph::new(self.buttons_content[n as usize].as_str())
-                    .block(Block::bordered().style(Style::new().red()));
-            } else {
-                button = Paragraph::new(self.buttons_content[n as usize].as_str())
-                    .block(Block::bordered());
-            }
-            button.render(horizontal_layout[n as usize], buf);
-        }
+        let buttons: Vec<(&str, Style)> = (0..self.number_of_buttons)
+            .map(|n| {
+                let style = if n == self.cursor_position {
+                    Style::new().red()
+                } else {
+                    Style::new()
+                };
+                (self.buttons_content[n as usize].as_str(), style)
+            })
+            .collect();
+        render_button_row(&buttons, vertical_layout[1], buf);
+    }
+}
+
+//Row of bordered buttons sharing the width of the area, the style is the one
+//of their border
+pub fn render_button_row(buttons: &[(&str, Style)], area: Rect, buf: &mut Buffer) {
+    if buttons.is_empty() {
+        return;
+    }
+    let horizontal_layout = Layout::default() //Layout on the bottom part for the different buttons
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Percentage((100 / buttons.len()) as u16);
+            buttons.len()
+        ])
+        .split(area);
+
+    for ((content, style), button_area) in buttons.iter().zip(horizontal_layout.iter()) {
+        Paragraph::new(*content)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().style(*style))
+            .render(*button_area, buf);
     }
 }
@@ -0,0 +1,170 @@
+// Bulk changes of the due dates around a break: the backlog of overdue reviews
+// is spread over the next days, the reviews due during a vacation are pushed
+// back, and before an exam the reviews due after it are brought forward. Only
+// the due dates of the items in review change, their intervals stay. Nothing
+// is logged: a replay of the review log gives the due dates of the reviews
+// back and so discards these changes.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::card::ReviewItem;
+use crate::deck::Deck;
+use crate::fsrs;
+use crate::scheduler::{days, CardState, Schedule};
+
+// Items recalled with a lower probability on the exam day are brought forward
+const ADVANCE_RETENTION: f64 = 0.95;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reschedule {
+    Spread(u32),            // Over this many days, the least likely to be recalled first
+    Postpone(u32),          // By this many days, those due before then
+    Advance(DateTime<Utc>), // Exam date
+}
+
+//Probability of recalling the item at the given time. Without a memory state
+//from FSRS, the interval is taken as the stability: 90% when it is due.
+pub fn retrievability(schedule: &Schedule, at: DateTime<Utc>) -> f64 {
+    let stability = if schedule.stability > 0.0 {
+        schedule.stability
+    } else {
+        schedule.interval.max(0.1)
+    };
+    let last_review = schedule
+        .last_review
+        .or(schedule.due.map(|due| due - days(schedule.interval)))
+        .unwrap_or(at);
+    let elapsed = ((at - last_review).num_seconds() as f64 / 86400.0).max(0.0);
+    fsrs::retrievability(elapsed, stability)
+}
+
+//Changes the due dates of the deck, returns the number of items moved
+pub fn reschedule(deck: &mut Deck, change: Reschedule, now: DateTime<Utc>) -> usize {
+    let items: Vec<(ReviewItem, Schedule)> = deck
+        .cards
+        .iter()
+        .filter(|card| !card.suspended)
+        .flat_map(|card| card.items())
+        .filter(|(_, schedule)| schedule.state == CardState::Review && schedule.due.is_some())
+        .collect();
+    let moves = match change {
+        Reschedule::Spread(days) => {
+            let overdue = items
+                .into_iter()
+                .filter(|(_, schedule)| schedule.due.unwrap() <= now)
+                .collect();
+            spread(overdue, now, days, now)
+        }
+        Reschedule::Postpone(days) => {
+            let end = now + Duration::days(days as i64);
+            items
+                .into_iter()
+                .filter(|(_, schedule)| schedule.due.unwrap() < end)
+                .map(|(item, schedule)| {
+                    let due = schedule.due.unwrap().max(now) + Duration::days(days as i64);
+                    (item, due)
+                })
+                .collect()
+        }
+        Reschedule::Advance(exam) => {
+            let days = (exam - now).num_days().max(1) as u32;
+            let late = items
+                .into_iter()
+                .filter(|(_, schedule)| {
+                    schedule.due.unwrap() > exam
+                        && retrievability(schedule, exam) < ADVANCE_RETENTION
+                })
+                .collect();
+            spread(late, exam, days, now)
+        }
+    };
+    let moved = moves.len();
+    for (item, due) in moves {
+        if let Some(card) = deck.card_mut(item.card_id) {
+            let mut schedule = card.schedule(item.ordinal);
+            schedule.due = Some(due);
+            card.set_schedule(item.ordinal, schedule);
+        }
+    }
+    moved
+}
+
+//New due dates of the items over the days from now, the least likely to be
+//recalled at the given time first. Those left for today are not moved.
+fn spread(
+    mut items: Vec<(ReviewItem, Schedule)>,
+    at: DateTime<Utc>,
+    days: u32,
+    now: DateTime<Utc>,
+) -> Vec<(ReviewItem, DateTime<Utc>)> {
+    items.sort_by(|(_, a), (_, b)| retrievability(a, at).total_cmp(&retrievability(b, at)));
+    let count = items.len();
+    items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, (item, schedule))| {
+            let day = (index * days.max(1) as usize / count) as i64;
+            let due = now + Duration::days(day);
+            (day > 0 || schedule.due.unwrap() > due).then_some((item, due))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use std::path::Path;
+
+    #[test]
+    fn spread_postpone_and_advance() {
+        let now = DateTime::<Utc>::from_timestamp(1_000 * 86400, 0).unwrap();
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        // Overdue by 1 to 4 days with an interval of 10 days, then one due in
+        // 2 days and one in 30 days
+        for (due_in, interval) in [
+            (-1, 10.0),
+            (-4, 10.0),
+            (-2, 10.0),
+            (-3, 10.0),
+            (2, 10.0),
+            (30, 60.0),
+        ] {
+            let mut card = Card::default();
+            card.set_schedule(
+                0,
+                Schedule {
+                    state: CardState::Review,
+                    due: Some(now + Duration::days(due_in)),
+                    interval,
+                    ..Schedule::default()
+                },
+            );
+            deck.add_card(card);
+        }
+        let dues = |deck: &Deck| -> Vec<i64> {
+            deck.cards
+                .iter()
+                .map(|card| (card.schedule(0).due.unwrap() - now).num_days())
+                .collect()
+        };
+        let mut spread = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        spread.cards = deck.cards.clone();
+        // The most overdue stay for today
+        assert_eq!(reschedule(&mut spread, Reschedule::Spread(2), now), 2);
+        assert_eq!(dues(&spread), vec![1, -4, 1, -3, 2, 30]);
+
+        let mut postponed = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        postponed.cards = deck.cards.clone();
+        assert_eq!(reschedule(&mut postponed, Reschedule::Postpone(7), now), 5);
+        assert_eq!(dues(&postponed), vec![7, 7, 7, 7, 9, 30]);
+
+        // Due in 30 days with an interval of 60 days, it would be recalled at
+        // 91% on the exam day
+        let exam = now + Duration::days(20);
+        let recall = retrievability(&deck.cards[5].schedule(0), exam);
+        assert!((recall - 0.91).abs() < 0.01);
+        assert_eq!(reschedule(&mut deck, Reschedule::Advance(exam), now), 1);
+        assert_eq!(dues(&deck), vec![-1, -4, -2, -3, 2, 0]);
+    }
+}
@@ -0,0 +1,291 @@
+// Every review of a deck, appended to <deck name>.log next to the deck file.
+// One review per line, fields separated by tabs:
+//   <card id>	<item ordinal>	<time, RFC 3339>	<grade, 1 (Again) to 4 (Easy)>	<time taken, ms>
+//   	<previous interval>	<new interval>	<ease>	<stability>	<difficulty>
+// The time taken and the schedule columns are missing in the logs written
+// before they were recorded, and the ordinal in those written before cards had
+// several items: their lines start with <card id>	<time>	<grade>.
+// The schedules can be rebuilt by replaying the log, with any scheduler. The
+// fuzzed intervals logged are kept as long as the scheduler gives them.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+
+use crate::card::ReviewItem;
+use crate::forecast::{self, FuzzSettings};
+use crate::scheduler::{Grade, Schedule, Scheduler, Steps};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Review {
+    pub item: ReviewItem,
+    pub time: DateTime<Utc>,
+    pub grade: Grade,
+    // From the moment the back was revealed to the grade, zero if unknown
+    pub time_taken: Duration,
+    pub change: Option<ScheduleChange>,
+}
+
+// Schedule of the item around the review, intervals in days
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleChange {
+    pub previous_interval: f64,
+    pub interval: f64,
+    pub ease: f64,
+    pub stability: f64,
+    pub difficulty: f64,
+}
+
+#[derive(Default)]
+pub struct ReviewLog {
+    path: PathBuf,
+    pub reviews: Vec<Review>,
+}
+
+impl Review {
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.item.card_id,
+            self.item.ordinal,
+            self.time.to_rfc3339(),
+            self.grade.number(),
+            self.time_taken.num_milliseconds()
+        );
+        if let Some(change) = &self.change {
+            line.push_str(&format!(
+                "\t{}\t{}\t{}\t{}\t{}",
+                change.previous_interval,
+                change.interval,
+                change.ease,
+                change.stability,
+                change.difficulty
+            ));
+        }
+        line
+    }
+
+    fn parse(line: &str) -> Option<Review> {
+        let mut fields = line.split('\t');
+        let card_id = fields.next()?.parse().ok()?;
+        let second = fields.next()?;
+        let (ordinal, time) = match DateTime::parse_from_rfc3339(second) {
+            Ok(time) => (0, time),
+            Err(_) => (
+                second.parse().ok()?,
+                DateTime::parse_from_rfc3339(fields.next()?).ok()?,
+            ),
+        };
+        let item = ReviewItem { card_id, ordinal };
+        let time = time.to_utc();
+        let grade = Grade::from_number(fields.next()?.parse().ok()?)?;
+        let time_taken = match fields.next() {
+            Some(milliseconds) => Duration::milliseconds(milliseconds.parse().ok()?),
+            None => Duration::zero(),
+        };
+        let change = match fields.next() {
+            Some(previous_interval) => Some(ScheduleChange {
+                previous_interval: previous_interval.parse().ok()?,
+                interval: fields.next()?.parse().ok()?,
+                ease: fields.next()?.parse().ok()?,
+                stability: fields.next()?.parse().ok()?,
+                difficulty: fields.next()?.parse().ok()?,
+            }),
+            None => None,
+        };
+        Some(Review {
+            item,
+            time,
+            grade,
+            time_taken,
+            change,
+        })
+    }
+}
+
+impl ScheduleChange {
+    pub fn new(previous: &Schedule, schedule: &Schedule) -> ScheduleChange {
+        ScheduleChange {
+            previous_interval: previous.interval,
+            interval: schedule.interval,
+            ease: schedule.ease,
+            stability: schedule.stability,
+            difficulty: schedule.difficulty,
+        }
+    }
+}
+
+impl ReviewLog {
+    //A missing file is an empty log
+    pub fn load(path: &Path) -> Result<ReviewLog> {
+        let mut reviews = Vec::new();
+        if path.exists() {
+            let text =
+                fs::read_to_string(path).wrap_err_with(|| format!("read {}", path.display()))?;
+            for (index, line) in text.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let review = Review::parse(line).ok_or_else(|| {
+                    eyre!("{} line {} : invalid review", path.display(), index + 1)
+                })?;
+                reviews.push(review);
+            }
+        }
+        Ok(ReviewLog {
+            path: path.to_path_buf(),
+            reviews,
+        })
+    }
+
+    pub fn set_path(&mut self, path: PathBuf) {
+        self.path = path;
+    }
+
+    //Appends the review to the file, the file is only ever cut by undo
+    pub fn append(&mut self, review: Review) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err_with(|| format!("open {}", self.path.display()))?;
+        writeln!(file, "{}", review.to_line())
+            .wrap_err_with(|| format!("write {}", self.path.display()))?;
+        self.reviews.push(review);
+        Ok(())
+    }
+
+    //Removes the last review, the file is cut before its line
+    pub fn remove_last(&mut self) -> Result<Option<Review>> {
+        let Some(review) = self.reviews.pop() else {
+            return Ok(None);
+        };
+        let text = fs::read_to_string(&self.path)
+            .wrap_err_with(|| format!("read {}", self.path.display()))?;
+        let trimmed = text.trim_end_matches('\n');
+        let length = trimmed.rfind('\n').map_or(0, |end| end + 1);
+        let file = OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .wrap_err_with(|| format!("open {}", self.path.display()))?;
+        file.set_len(length as u64)
+            .wrap_err_with(|| format!("write {}", self.path.display()))?;
+        Ok(Some(review))
+    }
+
+    //Schedules of the reviewed items after their reviews given again, in
+    //order, to the scheduler
+    pub fn replay(
+        &self,
+        scheduler: &dyn Scheduler,
+        steps: &Steps,
+        fuzz: &FuzzSettings,
+    ) -> HashMap<ReviewItem, Schedule> {
+        let mut schedules: HashMap<ReviewItem, Schedule> = HashMap::new();
+        for review in &self.reviews {
+            let schedule = schedules.entry(review.item).or_default();
+            *schedule = steps.review(scheduler, schedule, review.grade, review.time);
+            forecast::fuzz_replayed(fuzz, schedule, review);
+        }
+        schedules
+    }
+
+    pub fn delete_file(&self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)
+                .wrap_err_with(|| format!("delete {}", self.path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Sm2;
+
+    #[test]
+    fn line_round_trip() {
+        let review = Review {
+            item: ReviewItem {
+                card_id: 12,
+                ordinal: 1,
+            },
+            time: DateTime::<Utc>::from_timestamp(1700000000, 0).unwrap(),
+            grade: Grade::Hard,
+            time_taken: Duration::milliseconds(4250),
+            change: None,
+        };
+        assert_eq!(
+            review.to_line(),
+            "12\t1\t2023-11-14T22:13:20+00:00\t2\t4250"
+        );
+        assert_eq!(Review::parse(&review.to_line()), Some(review.clone()));
+        let review = Review {
+            change: Some(ScheduleChange {
+                previous_interval: 1.0,
+                interval: 2.5,
+                ease: 2.35,
+                stability: 0.0,
+                difficulty: 0.0,
+            }),
+            ..review
+        };
+        assert_eq!(
+            review.to_line(),
+            "12\t1\t2023-11-14T22:13:20+00:00\t2\t4250\t1\t2.5\t2.35\t0\t0"
+        );
+        assert_eq!(Review::parse(&review.to_line()), Some(review));
+        let old = Review::parse("12\t1\t2023-11-14T22:13:20+00:00\t2").unwrap();
+        assert_eq!(old.time_taken, Duration::zero());
+        assert_eq!(old.change, None);
+        // Before the ordinals
+        let oldest = Review::parse("12\t2023-11-14T22:13:20+00:00\t2").unwrap();
+        assert_eq!(
+            oldest.item,
+            ReviewItem {
+                card_id: 12,
+                ordinal: 0
+            }
+        );
+        assert_eq!(oldest.time, old.time);
+        assert_eq!(oldest.grade, Grade::Hard);
+        assert_eq!(
+            Review::parse("12\t1\t2023-11-14T22:13:20+00:00\t2\t4250\t1\t2.5"),
+            None
+        );
+        assert_eq!(Review::parse("12\t0\tyesterday\t2"), None);
+        assert_eq!(Review::parse("12\t0\t2023-11-14T22:13:20+00:00\t5"), None);
+    }
+
+    #[test]
+    fn replay() {
+        let item = ReviewItem {
+            card_id: 3,
+            ordinal: 0,
+        };
+        let start = DateTime::<Utc>::from_timestamp(1700000000, 0).unwrap();
+        let mut log = ReviewLog::default();
+        let (scheduler, steps) = (Sm2::default(), Steps::default());
+        let mut schedule = Schedule::default();
+        for (days, grade) in [(0, Grade::Good), (0, Grade::Good), (1, Grade::Again)] {
+            let time = start + Duration::days(days);
+            schedule = steps.review(&scheduler, &schedule, grade, time);
+            log.reviews.push(Review {
+                item,
+                time,
+                grade,
+                time_taken: Duration::zero(),
+                change: None,
+            });
+        }
+        let schedules = log.replay(&scheduler, &steps, &FuzzSettings::default());
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(schedules[&item], schedule);
+        assert_eq!(schedule.lapses, 1);
+    }
+}
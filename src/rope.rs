@@ -2,6 +2,7 @@
 // of balatui. The implementation is based on https://en.wikipedia.org/wiki/Rope_(data_structure)
 
 use std::fmt;
+use std::ops::Range;
 
 #[derive(Debug, Clone)]
 struct BadPath;
@@ -27,11 +28,15 @@ impl fmt::Display for Directions {
         }
     }
 }
-struct Rope {
+pub struct Rope {
     head: Option<Box<RopeNode>>,
     path_to_last: Vec<Directions>,
+    // Positions that follow the edits of the text, indexed by MarkId
+    marks: Vec<Option<usize>>,
 }
 
+pub type MarkId = usize;
+
 struct RopeNode {
     left_node: Option<Box<RopeNode>>,
     right_node: Option<Box<RopeNode>>,
@@ -42,6 +47,90 @@ struct RopeNode {
 }
 
 impl Rope {
+    pub fn new() -> Rope {
+        Rope {
+            head: None,
+            path_to_last: Vec::new(),
+            marks: Vec::new(),
+        }
+    }
+
+    //Adds a mark at the byte index. A mark stays on the same character
+    //through edits. Text inserted at a mark goes before it, like with a cursor.
+    pub fn add_mark(&mut self, index: usize) -> MarkId {
+        let index = index.min(self.len());
+        if let Some(free) = self.marks.iter().position(|mark| mark.is_none()) {
+            self.marks[free] = Some(index);
+            free
+        } else {
+            self.marks.push(Some(index));
+            self.marks.len() - 1
+        }
+    }
+
+    pub fn mark(&self, mark: MarkId) -> Option<usize> {
+        self.marks.get(mark).copied().flatten()
+    }
+
+    pub fn set_mark(&mut self, mark: MarkId, index: usize) {
+        let index = index.min(self.len());
+        if let Some(Some(position)) = self.marks.get_mut(mark) {
+            *position = index;
+        }
+    }
+
+    pub fn remove_mark(&mut self, mark: MarkId) {
+        if let Some(position) = self.marks.get_mut(mark) {
+            *position = None;
+        }
+    }
+
+    //lenght in bytes of the full string
+    pub fn len(&self) -> usize {
+        self.head
+            .as_ref()
+            .map_or(0, |head| (head.weight + head.right_weight) as usize)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //insert the text at the byte index, the index must be on a char boundary
+    pub fn insert(&mut self, index: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let index = index.min(self.len());
+        match self.head.as_mut() {
+            Some(head) => head.insert(index as u32, text),
+            None => self.elongate_rope(text.to_string()),
+        }
+        for position in self.marks.iter_mut().flatten() {
+            if *position >= index {
+                *position += text.len();
+            }
+        }
+    }
+
+    //delete the bytes in the range, the bounds must be on char boundaries
+    pub fn delete(&mut self, range: Range<usize>) {
+        let end = range.end.min(self.len());
+        if range.start >= end {
+            return;
+        }
+        if let Some(head) = self.head.as_mut() {
+            head.delete(range.start as u32, end as u32);
+        }
+        for position in self.marks.iter_mut().flatten() {
+            if *position >= end {
+                *position -= end - range.start;
+            } else if *position > range.start {
+                *position = range.start;
+            }
+        }
+    }
+
     //search for the string bit that contains the character at the index and
     //the index of said character in the string
     pub fn search(&self, index: u32) -> Option<(&String, u16)> {
@@ -147,16 +236,25 @@ impl Rope {
 
     //split a string in a new rope
     pub fn string_to_rope(mut input: String, leaf_weight: u8) -> Rope {
-        let mut new_rope = Rope {
-            head: None,
-            path_to_last: Vec::new(),
-        };
+        let mut new_rope = Rope::new();
         //Spilt the string bit by bit and put the bits in a node
         //Then put the node in the rope
         while input.len() > leaf_weight as usize {
             //Creation of the new node
+            //The split is moved back to a char boundary (or forward if the
+            //first char is longer than a leaf)
+            let mut split = leaf_weight as usize;
+            while split > 0 && !input.is_char_boundary(split) {
+                split -= 1;
+            }
+            if split == 0 {
+                split = input.chars().next().unwrap().len_utf8();
+                if split == input.len() {
+                    break;
+                }
+            }
             //Inversion of result/self for the function "split_off"
-            let temp = input.split_off(leaf_weight as usize);
+            let temp = input.split_off(split);
             let temp_content = input;
             input = temp;
             new_rope.elongate_rope(temp_content);
@@ -267,7 +365,73 @@ impl Rope {
     }
 }
 
+impl fmt::Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(head) = self.head.as_ref() {
+            head.write_content(f)?;
+        }
+        Ok(())
+    }
+}
+
 impl RopeNode {
+    fn write_content(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(content) = self.content.as_ref() {
+            f.write_str(content)?;
+        }
+        if let Some(left_node) = self.left_node.as_ref() {
+            left_node.write_content(f)?;
+        }
+        if let Some(right_node) = self.right_node.as_ref() {
+            right_node.write_content(f)?;
+        }
+        Ok(())
+    }
+
+    //index is relative to the string under this node
+    fn insert(&mut self, index: u32, text: &str) {
+        let text_weight = text.len() as u32;
+        if let Some(content) = self.content.as_mut() {
+            content.insert_str(index as usize, text);
+            self.weight += text_weight;
+        } else if index <= self.weight || self.right_node.is_none() {
+            // Appending at the end of the left part is preferred to keep the
+            // right part (and the path to the last node) untouched
+            self.weight += text_weight;
+            self.left_node.as_mut().unwrap().insert(index, text);
+        } else {
+            self.right_weight += text_weight;
+            self.right_node
+                .as_mut()
+                .unwrap()
+                .insert(index - self.weight, text);
+        }
+    }
+
+    //start and end are relative to the string under this node
+    fn delete(&mut self, start: u32, end: u32) {
+        if let Some(content) = self.content.as_mut() {
+            content.replace_range(start as usize..end as usize, "");
+            self.weight -= end - start;
+            return;
+        }
+        let left_weight = self.weight;
+        if start < left_weight {
+            let left_end = end.min(left_weight);
+            self.left_node.as_mut().unwrap().delete(start, left_end);
+            self.weight -= left_end - start;
+        }
+        if end > left_weight {
+            let right_start = start.max(left_weight) - left_weight;
+            let right_end = end - left_weight;
+            self.right_node
+                .as_mut()
+                .unwrap()
+                .delete(right_start, right_end);
+            self.right_weight -= right_end - right_start;
+        }
+    }
+
     pub fn new_empty(n_weight: u32, n_right_weight: u32, n_layer: u32) -> RopeNode {
         let node: RopeNode;
         node = Self {
@@ -625,6 +789,46 @@ mod tests {
         assert_eq!(rope.head.as_ref().unwrap().weight, 8);
     }
 
+    #[test]
+    fn insert_and_delete() {
+        let mut rope = Rope::string_to_rope(String::from_str("HelloWorld").unwrap(), 2);
+        rope.insert(5, " big ");
+        rope.insert(0, ">");
+        rope.insert(rope.len(), "!");
+        assert_eq!(rope.to_string(), ">Hello big World!");
+        assert_eq!(rope.len(), 17);
+        rope.delete(4..13);
+        assert_eq!(rope.to_string(), ">Helrld!");
+        rope.delete(0..rope.len());
+        assert!(rope.is_empty());
+        rope.insert(0, "again");
+        assert_eq!(rope.to_string(), "again");
+    }
+
+    #[test]
+    fn marks_follow_edits() {
+        let mut rope = Rope::string_to_rope(String::from_str("a b c").unwrap(), 2);
+        let first = rope.add_mark(2);
+        let second = rope.add_mark(4);
+        rope.insert(2, "xx");
+        assert_eq!(rope.mark(first), Some(4));
+        assert_eq!(rope.mark(second), Some(6));
+        rope.delete(1..5);
+        assert_eq!(rope.to_string(), "a c");
+        assert_eq!(rope.mark(first), Some(1));
+        assert_eq!(rope.mark(second), Some(2));
+        rope.remove_mark(first);
+        assert_eq!(rope.mark(first), None);
+        assert_eq!(rope.add_mark(0), first);
+    }
+
+    #[test]
+    fn multibyte_split() {
+        let rope = Rope::string_to_rope(String::from_str("ééé").unwrap(), 3);
+        assert_eq!(rope.to_string(), "ééé");
+        assert_eq!(rope.len(), 6);
+    }
+
     #[test]
     fn search() {
         let rope = Rope::string_to_rope(String::from_str("HelloWorld").unwrap(), 2);
@@ -0,0 +1,363 @@
+// Spaced repetition: after each review the grade given by the user decides
+// when the card is shown again. The longer a card is remembered, the longer the
+// interval before its next review.
+// New and forgotten cards first go through short learning steps (minutes),
+// the long term scheduler (SM-2 or FSRS) takes over when they graduate.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::fsrs::{Fsrs, FsrsParameters};
+
+// Longest interval in days, a hundred years, for all the schedulers
+pub const MAX_INTERVAL: f64 = 36500.0;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Grade {
+    Again,
+    Hard,
+    Good,
+    Easy,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CardState {
+    #[default]
+    New,
+    Learning,
+    Review,
+    Relearning, // Forgotten, going through the relearning steps
+}
+
+// Scheduling state of a card, saved with it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Schedule {
+    pub state: CardState,
+    pub step: u32,                  // Index of the current (re)learning step
+    pub due: Option<DateTime<Utc>>, // None for a card never reviewed
+    pub interval: f64,              // In days
+    pub ease: f64,
+    pub repetitions: u32, // Successful reviews in a row
+    pub lapses: u32,      // Times the card was forgotten after being learned
+    // Memory state used by FSRS, see fsrs.rs
+    pub stability: f64,
+    pub difficulty: f64,
+    pub last_review: Option<DateTime<Utc>>,
+}
+
+pub trait Scheduler {
+    //Schedule of the card after a review with the given grade
+    fn next(&self, schedule: &Schedule, grade: Grade, now: DateTime<Utc>) -> Schedule;
+}
+
+// Delay before a card in (re)learning comes back, written "30s", "10m", "1h"
+// or "2d" in the deck file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Step(pub Duration);
+
+// [steps] table of the deck file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Steps {
+    pub learning: Vec<Step>,
+    pub relearning: Vec<Step>,
+}
+
+// Algorithm used by a deck, the [scheduler] table of the deck file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum SchedulerSettings {
+    #[default]
+    Sm2,
+    Fsrs(FsrsParameters),
+}
+
+// The SM-2 algorithm of SuperMemo, with the grades mapped to the qualities
+// 3 (Hard), 4 (Good) and 5 (Easy). Again restarts the repetitions.
+pub struct Sm2 {
+    pub minimal_ease: f64,
+}
+
+impl Grade {
+    pub const ALL: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Grade::Again => "Again",
+            Grade::Hard => "Hard",
+            Grade::Good => "Good",
+            Grade::Easy => "Easy",
+        }
+    }
+
+    //Grades are given with the keys 1 to 4
+    pub fn from_key(c: char) -> Option<Grade> {
+        Grade::from_number(c.to_digit(10)?)
+    }
+
+    //1 for Again to 4 for Easy, as written in the review log
+    pub fn number(self) -> u32 {
+        self as u32 + 1
+    }
+
+    pub fn from_number(number: u32) -> Option<Grade> {
+        Grade::ALL.get(number.checked_sub(1)? as usize).copied()
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Schedule {
+        Schedule {
+            state: CardState::New,
+            step: 0,
+            due: None,
+            interval: 0.0,
+            ease: 2.5,
+            repetitions: 0,
+            lapses: 0,
+            stability: 0.0,
+            difficulty: 0.0,
+            last_review: None,
+        }
+    }
+}
+
+impl Schedule {
+    pub fn is_new(&self) -> bool {
+        self.state == CardState::New
+    }
+
+    pub fn is_learning(&self) -> bool {
+        matches!(self.state, CardState::Learning | CardState::Relearning)
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.due.is_none_or(|due| due <= now)
+    }
+}
+
+impl TryFrom<String> for Step {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Step, String> {
+        let invalid = || format!("invalid step \"{}\", expected e.g. \"10m\"", text);
+        let (unit_position, unit) = text.char_indices().next_back().ok_or_else(invalid)?;
+        let count: i64 = text[..unit_position].parse().map_err(|_| invalid())?;
+        let duration = match unit {
+            's' => Duration::try_seconds(count),
+            'm' => Duration::try_minutes(count),
+            'h' => Duration::try_hours(count),
+            'd' => Duration::try_days(count),
+            _ => None,
+        };
+        match duration {
+            Some(duration) if count > 0 => Ok(Step(duration)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl From<Step> for String {
+    fn from(step: Step) -> String {
+        let seconds = step.0.num_seconds();
+        for (unit, length) in [("d", 86400), ("h", 3600), ("m", 60)] {
+            if seconds % length == 0 {
+                return format!("{}{}", seconds / length, unit);
+            }
+        }
+        format!("{}s", seconds)
+    }
+}
+
+impl Default for Steps {
+    fn default() -> Steps {
+        Steps {
+            learning: vec![Step(Duration::minutes(1)), Step(Duration::minutes(10))],
+            relearning: vec![Step(Duration::minutes(10))],
+        }
+    }
+}
+
+impl Steps {
+    //Schedule after a review: the learning steps are applied to new and
+    //forgotten cards, the long term scheduler to the others
+    pub fn review(
+        &self,
+        scheduler: &dyn Scheduler,
+        schedule: &Schedule,
+        grade: Grade,
+        now: DateTime<Utc>,
+    ) -> Schedule {
+        match schedule.state {
+            CardState::New | CardState::Learning => {
+                self.step(&self.learning, scheduler, schedule, grade, now)
+            }
+            CardState::Relearning => self.step(&self.relearning, scheduler, schedule, grade, now),
+            CardState::Review => {
+                let mut next = scheduler.next(schedule, grade, now);
+                next.state = CardState::Review;
+                // The memory of the card is updated by the lapse, only the
+                // next review is moved to the first relearning step
+                if let (Grade::Again, Some(first)) = (grade, self.relearning.first()) {
+                    next.state = CardState::Relearning;
+                    next.step = 0;
+                    next.due = Some(now + first.0);
+                }
+                next
+            }
+        }
+    }
+
+    fn step(
+        &self,
+        steps: &[Step],
+        scheduler: &dyn Scheduler,
+        schedule: &Schedule,
+        grade: Grade,
+        now: DateTime<Utc>,
+    ) -> Schedule {
+        let step = match grade {
+            Grade::Again => 0,
+            Grade::Hard => schedule.step,
+            Grade::Good => schedule.step + 1,
+            Grade::Easy => steps.len() as u32,
+        };
+        let mut next = schedule.clone();
+        next.last_review = Some(now);
+        if let Some(delay) = steps.get(step as usize) {
+            if schedule.state == CardState::New {
+                next.state = CardState::Learning;
+            }
+            next.step = step;
+            next.due = now.checked_add_signed(delay.0);
+            return next;
+        }
+        // Graduation
+        if schedule.state == CardState::Relearning {
+            // The interval was already shortened by the lapse
+            next.due = now.checked_add_signed(days(schedule.interval));
+        } else {
+            let mut new = schedule.clone();
+            new.state = CardState::New;
+            next = scheduler.next(&new, grade, now);
+        }
+        next.state = CardState::Review;
+        next.step = 0;
+        next
+    }
+}
+
+impl SchedulerSettings {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SchedulerSettings::Sm2 => "SM-2",
+            SchedulerSettings::Fsrs(_) => "FSRS",
+        }
+    }
+
+    pub fn scheduler(&self) -> Box<dyn Scheduler> {
+        match self {
+            SchedulerSettings::Sm2 => Box::new(Sm2::default()),
+            SchedulerSettings::Fsrs(parameters) => Box::new(Fsrs::new(parameters.clone())),
+        }
+    }
+}
+
+impl Default for Sm2 {
+    fn default() -> Sm2 {
+        Sm2 { minimal_ease: 1.3 }
+    }
+}
+
+impl Scheduler for Sm2 {
+    fn next(&self, schedule: &Schedule, grade: Grade, now: DateTime<Utc>) -> Schedule {
+        let mut next = schedule.clone();
+        if grade == Grade::Again {
+            if schedule.repetitions > 0 {
+                next.lapses += 1;
+            }
+            next.repetitions = 0;
+            next.interval = 1.0;
+            next.ease = (schedule.ease - 0.2).max(self.minimal_ease);
+        } else {
+            let quality = grade as u32 + 2;
+            let distance = (5 - quality) as f64;
+            next.ease =
+                (schedule.ease + 0.1 - distance * (0.08 + distance * 0.02)).max(self.minimal_ease);
+            next.repetitions += 1;
+            next.interval = match next.repetitions {
+                1 => 1.0,
+                2 => 6.0,
+                _ => (schedule.interval * next.ease).round().min(MAX_INTERVAL),
+            };
+        }
+        next.due = now.checked_add_signed(days(next.interval));
+        next.last_review = Some(now);
+        next
+    }
+}
+
+//At most MAX_INTERVAL either way, intervals read from a deck can be larger
+pub fn days(days: f64) -> Duration {
+    Duration::seconds((days.clamp(-MAX_INTERVAL, MAX_INTERVAL) * 86400.0) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sm2_intervals() {
+        let sm2 = Sm2::default();
+        let now = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        let mut schedule = Schedule::default();
+        let mut intervals = Vec::new();
+        for grade in [Grade::Good, Grade::Good, Grade::Good, Grade::Easy] {
+            schedule = sm2.next(&schedule, grade, now);
+            intervals.push(schedule.interval);
+        }
+        assert_eq!(intervals, vec![1.0, 6.0, 15.0, 39.0]);
+        assert!((schedule.ease - 2.6).abs() < 1e-9);
+        assert_eq!(schedule.due, Some(now + days(39.0)));
+        schedule.interval = 30000.0;
+        schedule = sm2.next(&schedule, Grade::Easy, now);
+        assert_eq!(schedule.interval, MAX_INTERVAL);
+        assert_eq!(schedule.due, Some(now + days(MAX_INTERVAL)));
+        schedule = sm2.next(&schedule, Grade::Again, now);
+        assert_eq!((schedule.repetitions, schedule.lapses), (0, 1));
+        assert_eq!(schedule.interval, 1.0);
+        for _ in 0..20 {
+            schedule = sm2.next(&schedule, Grade::Hard, now);
+        }
+        assert_eq!(schedule.ease, sm2.minimal_ease);
+    }
+
+    #[test]
+    fn relearning_steps() {
+        let steps: Steps = toml::from_str("learning = []\nrelearning = [\"10m\", \"1d\"]").unwrap();
+        for invalid in ["", "10", "10é", "é", "0m", "99999999999999999d"] {
+            assert!(Step::try_from(String::from(invalid)).is_err());
+        }
+        assert_eq!(String::from(steps.relearning[1]), "1d");
+        assert!(toml::from_str::<Steps>("learning = [\"10x\"]").is_err());
+        let sm2 = Sm2::default();
+        let now = DateTime::<Utc>::from_timestamp(0, 0).unwrap();
+        // Without learning steps a new card graduates on its first review
+        let mut schedule = steps.review(&sm2, &Schedule::default(), Grade::Good, now);
+        assert_eq!(schedule.state, CardState::Review);
+        schedule = steps.review(&sm2, &schedule, Grade::Good, now);
+        assert_eq!(schedule.interval, 6.0);
+        schedule = steps.review(&sm2, &schedule, Grade::Again, now);
+        assert_eq!(schedule.state, CardState::Relearning);
+        assert_eq!(schedule.due, Some(now + Duration::minutes(10)));
+        assert_eq!(schedule.lapses, 1);
+        schedule = steps.review(&sm2, &schedule, Grade::Good, now);
+        assert_eq!((schedule.state, schedule.step), (CardState::Relearning, 1));
+        schedule = steps.review(&sm2, &schedule, Grade::Good, now);
+        assert_eq!(schedule.state, CardState::Review);
+        assert_eq!(schedule.due, Some(now + days(1.0)));
+    }
+}
@@ -0,0 +1,118 @@
+// Timing of the review sessions: the answer can be revealed after some time
+// and a session can be given a time budget. The summary shown at the end of a
+// session is computed from its reviews.
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::review_log::Review;
+use crate::scheduler::Grade;
+
+// [time] table of the deck file, 0 disables a limit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TimeLimits {
+    pub auto_reveal_seconds: u32,
+    pub session_minutes: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionSummary {
+    pub reviews: usize,
+    pub total_time: Duration,
+    // Number of reviews and time spent with each grade, in the order of Grade::ALL
+    pub grades: [(usize, Duration); 4],
+}
+
+impl TimeLimits {
+    pub fn auto_reveal(&self) -> Option<Duration> {
+        (self.auto_reveal_seconds > 0).then(|| Duration::seconds(self.auto_reveal_seconds as i64))
+    }
+
+    pub fn session_budget(&self) -> Option<Duration> {
+        (self.session_minutes > 0).then(|| Duration::minutes(self.session_minutes as i64))
+    }
+}
+
+impl SessionSummary {
+    pub fn new(reviews: &[Review]) -> SessionSummary {
+        let mut grades = [(0, Duration::zero()); 4];
+        for review in reviews {
+            let (count, time) = &mut grades[review.grade as usize];
+            *count += 1;
+            *time += review.time_taken;
+        }
+        SessionSummary {
+            reviews: reviews.len(),
+            total_time: grades.iter().map(|(_, time)| *time).sum(),
+            grades,
+        }
+    }
+
+    //Share of the reviews not graded Again
+    pub fn accuracy(&self) -> Option<f64> {
+        let forgotten = self.grades[Grade::Again as usize].0;
+        (self.reviews > 0).then(|| 1.0 - forgotten as f64 / self.reviews as f64)
+    }
+
+    pub fn average_time(&self) -> Option<Duration> {
+        average(self.total_time, self.reviews)
+    }
+
+    pub fn average_grade_time(&self, grade: Grade) -> Option<Duration> {
+        let (count, time) = self.grades[grade as usize];
+        average(time, count)
+    }
+}
+
+fn average(time: Duration, count: usize) -> Option<Duration> {
+    (count > 0).then(|| time / count as i32)
+}
+
+//"1:05" for 65 seconds, tenths of seconds under a minute
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.num_milliseconds() as f64 / 1000.0;
+    if seconds < 60.0 {
+        format!("{:.1}s", seconds)
+    } else {
+        let seconds = seconds.round() as i64;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::ReviewItem;
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn summary_of_the_reviews() {
+        let review = |grade: Grade, seconds: i64| Review {
+            item: ReviewItem {
+                card_id: 1,
+                ordinal: 0,
+            },
+            time: DateTime::<Utc>::from_timestamp(0, 0).unwrap(),
+            grade,
+            time_taken: Duration::seconds(seconds),
+            change: None,
+        };
+        let summary = SessionSummary::new(&[
+            review(Grade::Again, 20),
+            review(Grade::Good, 4),
+            review(Grade::Good, 8),
+            review(Grade::Easy, 0),
+        ]);
+        assert_eq!(summary.accuracy(), Some(0.75));
+        assert_eq!(summary.average_time(), Some(Duration::seconds(8)));
+        assert_eq!(
+            summary.average_grade_time(Grade::Good),
+            Some(Duration::seconds(6))
+        );
+        assert_eq!(summary.average_grade_time(Grade::Hard), None);
+        assert_eq!(SessionSummary::new(&[]).accuracy(), None);
+        assert_eq!(format_duration(Duration::milliseconds(4300)), "4.3s");
+        assert_eq!(format_duration(Duration::seconds(65)), "1:05");
+    }
+}
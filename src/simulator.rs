@@ -0,0 +1,370 @@
+// Simulation of the coming days of study under a scheduler and daily limits,
+// to compare settings before changing a deck. The learner is modeled with
+// FSRS: its weights are fitted to the review log of the deck and its memory of
+// each item comes from replaying the log. Synthetic learners start from new
+// cards with the default weights. At each review the item is recalled with the
+// probability given by the model, graded Good when it is and Again otherwise.
+// The intervals are not fuzzed and the draws start from the same seed, so a
+// simulation gives the same results each time.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::deck::Deck;
+use crate::fsrs::{self, Fsrs, FsrsParameters, DEFAULT_WEIGHTS};
+use crate::scheduler::{days, CardState, Grade, Schedule, SchedulerSettings, Steps};
+use crate::study_queue::DailyLimits;
+
+const SEED: u64 = 0x5eed;
+// Reviews of an item in a day, through its learning steps
+const MAX_DAILY_REVIEWS_OF_ITEM: usize = 20;
+pub const DESIRED_RETENTIONS: [f64; 4] = [0.8, 0.85, 0.9, 0.95];
+
+// What the learner remembers of an item
+#[derive(Debug, Clone, Copy)]
+struct Memory {
+    stability: f64,
+    difficulty: f64,
+    last_review: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+struct SimulatedItem {
+    memory: Option<Memory>,
+    schedule: Schedule,
+}
+
+pub struct Learner {
+    pub weights: [f64; 17],
+    pub fitted_reviews: Option<usize>, // Reviews the weights were fitted to
+    items: Vec<SimulatedItem>,
+}
+
+pub struct Configuration {
+    pub name: String,
+    pub scheduler: SchedulerSettings,
+    pub steps: Steps,
+    pub daily: DailyLimits,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DayResult {
+    pub reviews: usize,
+    pub new_cards: usize,
+    pub graded: usize, // Reviews of items in review, those counted in the retention
+    pub recalled: usize,
+    pub memorized: f64, // Expected number of items recalled at the end of the day
+}
+
+pub struct Simulation {
+    pub name: String,
+    pub days: Vec<DayResult>,
+}
+
+impl Learner {
+    pub fn from_deck(deck: &Deck) -> Learner {
+        let weights = match &deck.scheduler {
+            SchedulerSettings::Fsrs(parameters) => parameters.weights,
+            SchedulerSettings::Sm2 => DEFAULT_WEIGHTS,
+        };
+        let optimization = fsrs::optimize(&weights, &deck.review_log.reviews);
+        let fitted_reviews = optimization.as_ref().map(|o| o.predictions);
+        let weights = optimization.map_or(weights, |o| o.weights);
+        let replayed = deck.review_log.replay(
+            &Fsrs::new(FsrsParameters {
+                weights,
+                ..FsrsParameters::default()
+            }),
+            &deck.steps,
+            &deck.fuzz,
+        );
+        let items = deck
+            .cards
+            .iter()
+            .filter(|card| !card.suspended)
+            .flat_map(|card| card.items())
+            .map(|(item, schedule)| {
+                let memory = match replayed.get(&item) {
+                    Some(replayed) => memory_of(&weights, replayed),
+                    None => memory_of(&weights, &schedule),
+                };
+                SimulatedItem { memory, schedule }
+            })
+            .collect();
+        Learner {
+            weights,
+            fitted_reviews,
+            items,
+        }
+    }
+
+    pub fn synthetic(cards: usize) -> Learner {
+        let item = SimulatedItem {
+            memory: None,
+            schedule: Schedule::default(),
+        };
+        Learner {
+            weights: DEFAULT_WEIGHTS,
+            fitted_reviews: None,
+            items: vec![item; cards],
+        }
+    }
+
+    pub fn items(&self) -> usize {
+        self.items.len()
+    }
+}
+
+//Memory of a reviewed item, from its FSRS state or else its interval
+fn memory_of(weights: &[f64; 17], schedule: &Schedule) -> Option<Memory> {
+    if schedule.is_new() {
+        return None;
+    }
+    let last_review = schedule
+        .last_review
+        .or(schedule.due.map(|due| due - days(schedule.interval)))?;
+    let (stability, difficulty) = if schedule.stability > 0.0 {
+        (schedule.stability, schedule.difficulty)
+    } else {
+        let (stability, difficulty) = fsrs::initial_state(weights, Grade::Good);
+        (schedule.interval.max(stability), difficulty)
+    };
+    Some(Memory {
+        stability,
+        difficulty,
+        last_review,
+    })
+}
+
+//The schedulers compared by default: SM-2 and FSRS at several desired
+//retentions, with the steps and daily limits given
+pub fn configurations(learner: &Learner, steps: &Steps, daily: &DailyLimits) -> Vec<Configuration> {
+    let sm2 = Configuration {
+        name: String::from("SM-2"),
+        scheduler: SchedulerSettings::Sm2,
+        steps: steps.clone(),
+        daily: daily.clone(),
+    };
+    let fsrs = DESIRED_RETENTIONS.iter().map(|retention| Configuration {
+        name: format!("FSRS {:.0}%", retention * 100.0),
+        scheduler: SchedulerSettings::Fsrs(FsrsParameters {
+            weights: learner.weights,
+            desired_retention: *retention,
+        }),
+        steps: steps.clone(),
+        daily: daily.clone(),
+    });
+    std::iter::once(sm2).chain(fsrs).collect()
+}
+
+//Studies of the learner over the days from now with the configuration
+pub fn simulate(
+    learner: &Learner,
+    configuration: &Configuration,
+    days: u32,
+    now: DateTime<Utc>,
+) -> Simulation {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    let scheduler = configuration.scheduler.scheduler();
+    let steps = &configuration.steps;
+    let mut items = learner.items.clone();
+    let mut results = Vec::new();
+    for day in 0..days {
+        let start = now + Duration::days(day as i64);
+        let end = start + Duration::days(1);
+        let mut result = DayResult::default();
+        let mut due: Vec<usize> = (0..items.len())
+            .filter(|index| {
+                let schedule = &items[*index].schedule;
+                !schedule.is_new() && schedule.due.is_some_and(|due| due < end)
+            })
+            .collect();
+        due.sort_by_key(|index| items[*index].schedule.due);
+        // Like in the study queue, only the reviews are limited, the items
+        // in learning always come back
+        let mut reviews = 0;
+        due.retain(|index| {
+            if items[*index].schedule.state != CardState::Review {
+                return true;
+            }
+            reviews += 1;
+            reviews <= configuration.daily.reviews
+        });
+        let new = (0..items.len())
+            .filter(|index| items[*index].schedule.is_new())
+            .take(configuration.daily.new_cards);
+        let studied: Vec<usize> = due.into_iter().chain(new).collect();
+        for index in studied {
+            let item = &mut items[index];
+            if item.schedule.is_new() {
+                result.new_cards += 1;
+            }
+            let mut time = item.schedule.due.unwrap_or(start).max(start);
+            for _ in 0..MAX_DAILY_REVIEWS_OF_ITEM {
+                let in_review = item.schedule.state == CardState::Review;
+                let grade = review(&learner.weights, item, time, &mut rng);
+                item.schedule = steps.review(scheduler.as_ref(), &item.schedule, grade, time);
+                result.reviews += 1;
+                if in_review {
+                    result.graded += 1;
+                    result.recalled += (grade != Grade::Again) as usize;
+                }
+                match item.schedule.due {
+                    Some(due) if due < end && item.schedule.state != CardState::Review => {
+                        time = due.max(time)
+                    }
+                    _ => break,
+                }
+            }
+        }
+        result.memorized = items
+            .iter()
+            .filter_map(|item| item.memory)
+            .map(|memory| recall_probability(&memory, end))
+            .sum();
+        results.push(result);
+    }
+    Simulation {
+        name: configuration.name.clone(),
+        days: results,
+    }
+}
+
+fn recall_probability(memory: &Memory, at: DateTime<Utc>) -> f64 {
+    let elapsed = ((at - memory.last_review).num_seconds() as f64 / 86400.0).max(0.0);
+    fsrs::retrievability(elapsed, memory.stability)
+}
+
+//Grade of the learner for the item, whose memory is updated. A new item is
+//learned when first seen.
+fn review(
+    weights: &[f64; 17],
+    item: &mut SimulatedItem,
+    time: DateTime<Utc>,
+    rng: &mut impl Rng,
+) -> Grade {
+    let (grade, (stability, difficulty)) = match item.memory {
+        None => (Grade::Good, fsrs::initial_state(weights, Grade::Good)),
+        Some(memory) => {
+            let probability = recall_probability(&memory, time);
+            let grade = if rng.gen_bool(probability.clamp(0.0, 1.0)) {
+                Grade::Good
+            } else {
+                Grade::Again
+            };
+            let state = fsrs::next_state(
+                weights,
+                memory.stability,
+                memory.difficulty,
+                probability,
+                grade,
+            );
+            (grade, state)
+        }
+    };
+    item.memory = Some(Memory {
+        stability,
+        difficulty,
+        last_review: time,
+    });
+    grade
+}
+
+impl Simulation {
+    pub fn total_reviews(&self) -> usize {
+        self.days.iter().map(|day| day.reviews).sum()
+    }
+
+    pub fn peak_reviews(&self) -> usize {
+        self.days.iter().map(|day| day.reviews).max().unwrap_or(0)
+    }
+
+    //Share of the reviews of items in review that were recalled
+    pub fn retention(&self) -> f64 {
+        retention(&self.days)
+    }
+
+    pub fn memorized(&self) -> f64 {
+        self.days.last().map_or(0.0, |day| day.memorized)
+    }
+}
+
+fn retention(days: &[DayResult]) -> f64 {
+    let graded: usize = days.iter().map(|day| day.graded).sum();
+    let recalled: usize = days.iter().map(|day| day.recalled).sum();
+    recalled as f64 / graded.max(1) as f64
+}
+
+//Summary of the simulations side by side, then the reviews per day and the
+//retention of each week
+pub fn report(simulations: &[Simulation]) -> String {
+    let mut lines = Vec::new();
+    let row = |name: &str, cell: &dyn Fn(&Simulation) -> String| {
+        let cells: String = simulations
+            .iter()
+            .map(|simulation| format!("{:>12}", cell(simulation)))
+            .collect();
+        format!("{:<12}{}", name, cells)
+    };
+    lines.push(row("", &|simulation| simulation.name.clone()));
+    lines.push(row("Reviews", &|s| s.total_reviews().to_string()));
+    lines.push(row("Per day", &|s| {
+        format!(
+            "{:.1}",
+            s.total_reviews() as f64 / s.days.len().max(1) as f64
+        )
+    }));
+    lines.push(row("Peak", &|s| s.peak_reviews().to_string()));
+    lines.push(row("Retention", &|s| {
+        format!("{:.1}%", s.retention() * 100.0)
+    }));
+    lines.push(row("Memorized", &|s| format!("{:.0}", s.memorized())));
+    lines.push(String::new());
+    lines.push(String::from("Reviews per day and retention of each week"));
+    let weeks = simulations.first().map_or(0, |s| s.days.len().div_ceil(7));
+    for week in 0..weeks {
+        lines.push(row(&format!("Week {}", week + 1), &|s| {
+            let days = &s.days[week * 7..((week + 1) * 7).min(s.days.len())];
+            let reviews: usize = days.iter().map(|day| day.reviews).sum();
+            let retention = if days.iter().any(|day| day.graded > 0) {
+                format!("{:.0}%", retention(days) * 100.0)
+            } else {
+                String::from("-")
+            };
+            format!("{:.0} {:>4}", reviews as f64 / days.len() as f64, retention)
+        }));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn higher_retention_costs_more_reviews() {
+        let now = DateTime::<Utc>::from_timestamp(1_000 * 86400, 0).unwrap();
+        let learner = Learner::synthetic(200);
+        let configurations = configurations(&learner, &Steps::default(), &DailyLimits::default());
+        let simulations: Vec<Simulation> = configurations
+            .iter()
+            .map(|configuration| simulate(&learner, configuration, 60, now))
+            .collect();
+        let [_, low, _, medium, high] = &simulations[..] else {
+            panic!("5 configurations expected");
+        };
+        for simulation in &simulations {
+            let new: usize = simulation.days.iter().map(|day| day.new_cards).sum();
+            assert_eq!(new, 200);
+        }
+        assert!(low.total_reviews() < medium.total_reviews());
+        assert!(medium.total_reviews() < high.total_reviews());
+        assert!(low.retention() < high.retention());
+        assert!((medium.retention() - 0.9).abs() < 0.05);
+        // The same draws each time
+        let again = simulate(&learner, &configurations[3], 60, now);
+        assert_eq!(again.days, medium.days);
+        assert!(report(&simulations).contains("FSRS 95%"));
+    }
+}
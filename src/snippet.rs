@@ -0,0 +1,281 @@
+// Snippets of the edit mode, UltiSnips style. A snippet body contains tab stops:
+//   $1, $2, ...      places where the cursor goes on <Tab>, in order
+//   ${1:default}     tab stop with a default text
+//   $0               last position of the cursor
+// A tab stop number written several times is mirrored: the first occurrence is
+// edited and the others copy it. A "$" that does not start a tab stop is kept
+// as is, so LaTeX math like "$$1$" needs no escaping.
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::rope::Rope;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Snippet {
+    pub text: String,
+    // (tab stop number, place in text) in order of appearance
+    pub stops: Vec<(u32, Range<usize>)>,
+}
+
+enum Segment {
+    Literal(String),
+    Stop(u32, Option<String>),
+}
+
+// Snippet being filled in the buffer
+pub struct ActiveSnippet {
+    // In jump order, the first range of a stop is the one edited, the others
+    // are its mirrors
+    stops: Vec<Vec<Range<usize>>>,
+    current: usize,
+}
+
+pub struct Snippets {
+    bodies: HashMap<String, String>,
+}
+
+impl Snippets {
+    pub fn new(bodies: HashMap<String, String>) -> Snippets {
+        Snippets { bodies }
+    }
+
+    //Finds the longest trigger ending the text before the cursor. A trigger
+    //must not follow a letter, a digit or a backslash, so that "ff" does not
+    //expand inside "\diff". Returns the trigger length and the snippet.
+    pub fn find(&self, before_cursor: &str) -> Option<(usize, Snippet)> {
+        self.bodies
+            .iter()
+            .filter(|(trigger, _)| !trigger.is_empty() && before_cursor.ends_with(trigger.as_str()))
+            .filter(|(trigger, _)| {
+                before_cursor[..before_cursor.len() - trigger.len()]
+                    .chars()
+                    .next_back()
+                    .is_none_or(|c| !c.is_alphanumeric() && c != '\\')
+            })
+            .max_by_key(|(trigger, _)| trigger.len())
+            .map(|(trigger, body)| (trigger.len(), Snippet::parse(body)))
+    }
+}
+
+impl Snippet {
+    pub fn parse(body: &str) -> Snippet {
+        let segments = Snippet::segments(body);
+        let mut defaults: HashMap<u32, &str> = HashMap::new();
+        for segment in segments.iter() {
+            if let Segment::Stop(number, Some(default)) = segment {
+                defaults.entry(*number).or_insert(default.as_str());
+            }
+        }
+        let mut text = String::new();
+        let mut stops = Vec::new();
+        for segment in segments.iter() {
+            match segment {
+                Segment::Literal(literal) => text.push_str(literal),
+                Segment::Stop(number, _) => {
+                    let start = text.len();
+                    text.push_str(defaults.get(number).unwrap_or(&""));
+                    stops.push((*number, start..text.len()));
+                }
+            }
+        }
+        Snippet { text, stops }
+    }
+
+    fn segments(body: &str) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut rest = body;
+        while let Some(c) = rest.chars().next() {
+            if let Some((stop, length)) = Snippet::parse_stop(rest) {
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(stop);
+                rest = &rest[length..];
+            } else {
+                literal.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        segments
+    }
+
+    //Parses a tab stop at the start of the text, returns it with its length
+    fn parse_stop(text: &str) -> Option<(Segment, usize)> {
+        let after_dollar = text.strip_prefix('$')?;
+        let digits = after_dollar
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits > 0 {
+            let number = after_dollar[..digits].parse().ok()?;
+            return Some((Segment::Stop(number, None), 1 + digits));
+        }
+        let inside = after_dollar.strip_prefix('{')?;
+        let digits = inside.chars().take_while(|c| c.is_ascii_digit()).count();
+        if digits == 0 {
+            return None;
+        }
+        let number = inside[..digits].parse().ok()?;
+        let after_number = &inside[digits..];
+        if after_number.starts_with('}') {
+            return Some((Segment::Stop(number, None), 2 + digits + 1));
+        }
+        let default = after_number.strip_prefix(':')?;
+        // The default text can contain LaTeX groups, look for the matching brace
+        let mut depth = 0;
+        for (position, c) in default.char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    let stop = Segment::Stop(number, Some(default[..position].to_string()));
+                    return Some((stop, 2 + digits + 1 + position + 1));
+                }
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+impl ActiveSnippet {
+    //Starts a snippet whose text was inserted at offset in the buffer.
+    //Returns None if the snippet has no tab stop to jump to.
+    pub fn new(snippet: &Snippet, offset: usize) -> Option<ActiveSnippet> {
+        let mut numbers: Vec<u32> = snippet.stops.iter().map(|(number, _)| *number).collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        // $0 is the last stop, not the first
+        if numbers.first() == Some(&0) {
+            numbers.rotate_left(1);
+        }
+        let stops: Vec<Vec<Range<usize>>> = numbers
+            .iter()
+            .map(|number| {
+                snippet
+                    .stops
+                    .iter()
+                    .filter(|(stop_number, _)| stop_number == number)
+                    .map(|(_, range)| range.start + offset..range.end + offset)
+                    .collect()
+            })
+            .collect();
+        if stops.is_empty() {
+            return None;
+        }
+        Some(ActiveSnippet { stops, current: 0 })
+    }
+
+    //Text of the buffer edited in the current tab stop
+    pub fn current_range(&self) -> Range<usize> {
+        self.stops[self.current][0].clone()
+    }
+
+    //Returns false when there is no tab stop left
+    pub fn next(&mut self) -> bool {
+        self.current += 1;
+        self.current < self.stops.len()
+    }
+
+    pub fn previous(&mut self) {
+        self.current = self.current.saturating_sub(1);
+    }
+
+    pub fn is_last(&self) -> bool {
+        self.current + 1 == self.stops.len()
+    }
+
+    //Must be called after each edit of the buffer: the bytes in range were
+    //replaced by length bytes. Returns false if the edit happened outside of
+    //the current tab stop, which ends the snippet.
+    pub fn text_replaced(&mut self, range: Range<usize>, length: usize) -> bool {
+        let current = self.current_range();
+        if range.start < current.start || range.end > current.end {
+            return false;
+        }
+        self.shift(range, length, (self.current, 0));
+        true
+    }
+
+    //Copies the current tab stop in its mirrors
+    pub fn update_mirrors(&mut self, buffer: &mut Rope) {
+        for mirror in 1..self.stops[self.current].len() {
+            let text = buffer.to_string();
+            let edited = text[self.current_range()].to_string();
+            let range = self.stops[self.current][mirror].clone();
+            if text[range.clone()] != edited {
+                buffer.delete(range.clone());
+                buffer.insert(range.start, &edited);
+                self.shift(range, edited.len(), (self.current, mirror));
+            }
+        }
+    }
+
+    //Moves the ranges after a replacement, the owner range is the one that
+    //contains the replacement
+    fn shift(&mut self, replaced: Range<usize>, length: usize, owner: (usize, usize)) {
+        let removed = replaced.end - replaced.start;
+        for (stop_index, stop) in self.stops.iter_mut().enumerate() {
+            for (range_index, range) in stop.iter_mut().enumerate() {
+                if (stop_index, range_index) == owner {
+                    range.end = range.end + length - removed;
+                } else if range.start >= replaced.end {
+                    range.start = range.start + length - removed;
+                    range.end = range.end + length - removed;
+                } else if range.end > replaced.start {
+                    range.end = (range.end + length).saturating_sub(removed);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_body() {
+        let snippet = Snippet::parse("\\frac{${1:a}}{$2}$0");
+        assert_eq!(snippet.text, "\\frac{a}{}");
+        assert_eq!(snippet.stops, vec![(1, 6..7), (2, 9..9), (0, 10..10)]);
+        let snippet = Snippet::parse("$$1$ ${2:\\mathbb{R}}");
+        assert_eq!(snippet.text, "$$ \\mathbb{R}");
+        assert_eq!(snippet.stops, vec![(1, 1..1), (2, 3..13)]);
+    }
+
+    #[test]
+    fn trigger_boundary() {
+        let snippets = Snippets::new(HashMap::from([(
+            String::from("ff"),
+            String::from("\\frac{$1}{$2}"),
+        )]));
+        assert!(snippets.find("$ff").is_some());
+        assert!(snippets.find("\\diff").is_none());
+        assert!(snippets.find("off").is_none());
+    }
+
+    #[test]
+    fn mirrors_follow_edits() {
+        let snippet = Snippet::parse("\\begin{$1}$0\\end{$1}");
+        let mut buffer = Rope::string_to_rope(String::from_str("x ").unwrap(), 64);
+        buffer.insert(2, &snippet.text);
+        let mut active = ActiveSnippet::new(&snippet, 2).unwrap();
+        for c in ["a", "l", "i", "g", "n"] {
+            let position = active.current_range().end;
+            buffer.insert(position, c);
+            assert!(active.text_replaced(position..position, 1));
+            active.update_mirrors(&mut buffer);
+        }
+        assert_eq!(buffer.to_string(), "x \\begin{align}\\end{align}");
+        assert!(active.next());
+        assert!(active.is_last());
+        assert_eq!(active.current_range(), 15..15);
+    }
+}
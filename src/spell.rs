@@ -0,0 +1,235 @@
+// Spell checking of the prose of the cards against a local Hunspell dictionary
+// (<name>.aff and <name>.dic files). LaTeX commands, their non textual
+// arguments, comments and math are not checked.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use spellbook::Dictionary;
+
+// Commands whose first argument is a name and not text
+const NAME_ARGUMENT_COMMANDS: [&str; 12] = [
+    "begin",
+    "end",
+    "label",
+    "ref",
+    "eqref",
+    "cite",
+    "usepackage",
+    "documentclass",
+    "includegraphics",
+    "url",
+    "href",
+    "input",
+];
+
+// Environments whose whole content is math
+const MATH_ENVIRONMENTS: [&str; 9] = [
+    "equation",
+    "align",
+    "gather",
+    "multline",
+    "math",
+    "displaymath",
+    "eqnarray",
+    "flalign",
+    "alignat",
+];
+
+pub struct SpellChecker {
+    dictionary: Dictionary,
+}
+
+impl SpellChecker {
+    //Loads <path>.aff and <path>.dic, path is given without extension
+    pub fn load(path: &Path) -> Result<SpellChecker> {
+        let aff_path = with_added_extension(path, "aff");
+        let dic_path = with_added_extension(path, "dic");
+        let aff = std::fs::read_to_string(&aff_path)
+            .wrap_err_with(|| format!("read {}", aff_path.display()))?;
+        let dic = std::fs::read_to_string(&dic_path)
+            .wrap_err_with(|| format!("read {}", dic_path.display()))?;
+        let dictionary = Dictionary::new(&aff, &dic)
+            .map_err(|error| eyre!("parse dictionary {} : {}", path.display(), error))?;
+        Ok(SpellChecker { dictionary })
+    }
+
+    //Byte ranges of the misspelled words of the text
+    pub fn misspelled(&self, text: &str) -> Vec<Range<usize>> {
+        prose_words(text)
+            .into_iter()
+            .filter(|word| !self.dictionary.check(&text[word.clone()]))
+            .collect()
+    }
+
+    pub fn suggest(&self, word: &str) -> Vec<String> {
+        let mut suggestions = Vec::new();
+        self.dictionary.suggest(word, &mut suggestions);
+        suggestions
+    }
+}
+
+//The name of a dictionary can contain dots, like "de_DE.frami", so the
+//extension is appended and not replaced
+fn with_added_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".");
+    path.push(extension);
+    PathBuf::from(path)
+}
+
+//Byte ranges of the words written in prose. Single letters are skipped, they
+//are most of the time variables written outside of math.
+pub fn prose_words(text: &str) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut position = 0;
+    while let Some(c) = text[position..].chars().next() {
+        let rest = &text[position..];
+        if c == '%' {
+            position += rest.find('\n').unwrap_or(rest.len());
+        } else if c == '\\' {
+            position = skip_command(text, position);
+        } else if let Some(inside) = rest.strip_prefix("$$") {
+            position += 2 + inside.find("$$").map_or(inside.len(), |end| end + 2);
+        } else if c == '$' {
+            position += 1 + find_unescaped(&rest[1..], '$').map_or(rest.len() - 1, |end| end + 1);
+        } else if c.is_alphabetic() {
+            let length = word_length(rest);
+            if rest[..length].chars().count() > 1 {
+                words.push(position..position + length);
+            }
+            position += length;
+        } else {
+            position += c.len_utf8();
+        }
+    }
+    words
+}
+
+//Letters, with apostrophes inside the word ("don't")
+fn word_length(text: &str) -> usize {
+    let mut length = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let inner_apostrophe =
+            (c == '\'' || c == '’') && chars.peek().is_some_and(|(_, next)| next.is_alphabetic());
+        if !c.is_alphabetic() && !inner_apostrophe {
+            break;
+        }
+        length = position + c.len_utf8();
+    }
+    length
+}
+
+fn find_unescaped(text: &str, searched: char) -> Option<usize> {
+    let mut escaped = false;
+    for (position, c) in text.char_indices() {
+        if c == searched && !escaped {
+            return Some(position);
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    None
+}
+
+//Returns the position after the command starting at position, including the
+//parts of its arguments that are not prose
+fn skip_command(text: &str, position: usize) -> usize {
+    let after_backslash = position + 1;
+    let rest = &text[after_backslash..];
+    let name_length = rest
+        .char_indices()
+        .find(|(_, c)| !c.is_ascii_alphabetic())
+        .map_or(rest.len(), |(end, _)| end);
+    if name_length == 0 {
+        return match rest.chars().next() {
+            Some('(') => skip_to(text, after_backslash + 1, "\\)"),
+            Some('[') => skip_to(text, after_backslash + 1, "\\]"),
+            Some(c) => after_backslash + c.len_utf8(),
+            None => after_backslash,
+        };
+    }
+    let name = &rest[..name_length];
+    let mut end = after_backslash + name_length;
+    if !NAME_ARGUMENT_COMMANDS.contains(&name) {
+        return end;
+    }
+    end += text[end..].len() - text[end..].trim_start().len();
+    if text[end..].starts_with('[') {
+        end = skip_group(text, end, '[', ']');
+    }
+    if !text[end..].starts_with('{') {
+        return end;
+    }
+    let argument_end = skip_group(text, end, '{', '}');
+    let environment = text[end + 1..argument_end]
+        .trim_end_matches('}')
+        .trim_end_matches('*');
+    if name == "begin" && MATH_ENVIRONMENTS.contains(&environment) {
+        let closing = format!("\\end{{{}", environment);
+        return skip_to(text, argument_end, &closing);
+    }
+    argument_end
+}
+
+//Position after the next occurrence of the pattern, or the end of the text
+fn skip_to(text: &str, position: usize, pattern: &str) -> usize {
+    text[position..]
+        .find(pattern)
+        .map_or(text.len(), |found| position + found + pattern.len())
+}
+
+//Position after the group opened at position, nested groups included
+fn skip_group(text: &str, position: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    for (offset, c) in text[position..].char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return position + offset + c.len_utf8();
+            }
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<&str> {
+        prose_words(text)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect()
+    }
+
+    #[test]
+    fn latex_is_skipped() {
+        assert_eq!(
+            words("Let $x \\in \\mathbb{R}$ be the \\emph{real} don't % comment"),
+            vec!["Let", "be", "the", "real", "don't"]
+        );
+        assert_eq!(
+            words(
+                "See \\ref{eq:main} and\n\\begin{align*}\nx &= y\n\\end{align*}\nthen $$ab$$ done"
+            ),
+            vec!["See", "and", "then", "done"]
+        );
+        assert_eq!(
+            words("Nested \\( a b \\) and \\[ cd \\] end"),
+            vec!["Nested", "and", "end"]
+        );
+    }
+
+    #[test]
+    fn dictionary_names_keep_their_dots() {
+        assert_eq!(
+            with_added_extension(Path::new("/usr/share/hunspell/de_DE.frami"), "aff"),
+            PathBuf::from("/usr/share/hunspell/de_DE.frami.aff")
+        );
+    }
+}
@@ -0,0 +1,305 @@
+// Cards studied in a session: the learning cards due now, then the reviews
+// due today and the new cards, both within the daily limits of the deck. A
+// study day starts at the rollover hour (local time), not at midnight, so that
+// a late session counts for the day it started.
+// Once an item of a card is studied, its siblings are buried until the next
+// day: the reverse of a card would give its answer away. Suspended cards are
+// never studied.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::card::ReviewItem;
+use crate::deck::Deck;
+use crate::review_log::Review;
+use crate::scheduler::{CardState, Schedule};
+
+// [daily] table of the deck file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DailyLimits {
+    pub new_cards: usize,
+    pub reviews: usize,
+    pub new_card_order: NewCardOrder,
+    #[serde(deserialize_with = "deserialize_rollover_hour")]
+    pub rollover_hour: u32, // From 0 to 23
+}
+
+// Where the new cards go in the queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NewCardOrder {
+    Before,
+    After,
+    #[default]
+    Interleaved,
+}
+
+// Cards left to study today, shown on the deck tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueCounts {
+    pub new: usize,
+    pub learning: usize,
+    pub due: usize,
+}
+
+impl Default for DailyLimits {
+    fn default() -> DailyLimits {
+        DailyLimits {
+            new_cards: 20,
+            reviews: 200,
+            new_card_order: NewCardOrder::Interleaved,
+            rollover_hour: 4,
+        }
+    }
+}
+
+fn deserialize_rollover_hour<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let hour = u32::deserialize(deserializer)?;
+    if hour < 24 {
+        Ok(hour)
+    } else {
+        Err(D::Error::custom(format!(
+            "invalid rollover hour {}, expected 0 to 23",
+            hour
+        )))
+    }
+}
+
+//Start of the study day containing now
+pub fn day_start<Tz: TimeZone>(
+    now: DateTime<Utc>,
+    rollover_hour: u32,
+    timezone: &Tz,
+) -> DateTime<Utc> {
+    let rollover = Duration::hours(rollover_hour as i64);
+    let shifted = now.with_timezone(timezone).naive_local() - rollover;
+    let start = shifted.date().and_hms_opt(0, 0, 0).unwrap() + rollover;
+    // The rollover can fall in a daylight saving gap, the day then starts a
+    // day before now at worst
+    timezone
+        .from_local_datetime(&start)
+        .earliest()
+        .map_or(now - Duration::days(1), |start| start.with_timezone(&Utc))
+}
+
+//New items introduced and reviews done since the start of the day. A review
+//is counted once per item and per day, the learning steps of an item are free.
+fn studied_since(reviews: &[Review], start: DateTime<Utc>) -> (usize, usize) {
+    let mut first_reviews: HashMap<ReviewItem, DateTime<Utc>> = HashMap::new();
+    for review in reviews {
+        first_reviews
+            .entry(review.item)
+            .and_modify(|first| *first = (*first).min(review.time))
+            .or_insert(review.time);
+    }
+    let mut new = 0;
+    let mut reviewed = 0;
+    for first in first_reviews.values() {
+        if *first >= start {
+            new += 1;
+        }
+    }
+    let mut counted = HashSet::new();
+    for review in reviews.iter().filter(|review| review.time >= start) {
+        if first_reviews[&review.item] < start && counted.insert(review.item) {
+            reviewed += 1;
+        }
+    }
+    (new, reviewed)
+}
+
+//Items of the session, grouped as (learning, reviews, new)
+fn split_queue(
+    deck: &Deck,
+    now: DateTime<Utc>,
+    timezone: &impl TimeZone,
+) -> (Vec<ReviewItem>, Vec<ReviewItem>, Vec<ReviewItem>) {
+    let limits = &deck.daily;
+    let start = day_start(now, limits.rollover_hour, timezone);
+    let end = start + Duration::days(1);
+    let (new_done, reviews_done) = studied_since(&deck.review_log.reviews, start);
+
+    let mut studied_today: HashMap<u64, HashSet<u32>> = HashMap::new();
+    for review in deck
+        .review_log
+        .reviews
+        .iter()
+        .filter(|review| review.time >= start)
+    {
+        studied_today
+            .entry(review.item.card_id)
+            .or_default()
+            .insert(review.item.ordinal);
+    }
+    let buried = |item: &ReviewItem| {
+        studied_today
+            .get(&item.card_id)
+            .is_some_and(|ordinals| ordinals.iter().any(|ordinal| *ordinal != item.ordinal))
+    };
+
+    let items: Vec<(ReviewItem, Schedule)> = deck
+        .cards
+        .iter()
+        .filter(|card| !card.suspended)
+        .flat_map(|card| card.items())
+        .collect();
+    let mut learning: Vec<_> = items
+        .iter()
+        .filter(|(_, schedule)| schedule.is_learning() && schedule.is_due(now))
+        .collect();
+    learning.sort_by_key(|(_, schedule)| schedule.due);
+    let mut reviews: Vec<_> = items
+        .iter()
+        .filter(|(_, schedule)| schedule.state == CardState::Review)
+        .filter(|(_, schedule)| schedule.due.is_some_and(|due| due < end))
+        .collect();
+    // The most overdue first
+    reviews.sort_by_key(|(_, schedule)| schedule.due);
+    // At most one item per card, the others would be buried by its review
+    let mut cards: HashSet<u64> = learning.iter().map(|(item, _)| item.card_id).collect();
+    reviews.retain(|(item, _)| !buried(item) && cards.insert(item.card_id));
+    reviews.truncate(limits.reviews.saturating_sub(reviews_done));
+    let new: Vec<_> = items
+        .iter()
+        .filter(|(_, schedule)| schedule.is_new())
+        .filter(|(item, _)| !buried(item) && cards.insert(item.card_id))
+        .take(limits.new_cards.saturating_sub(new_done))
+        .collect();
+
+    let ids = |items: Vec<&(ReviewItem, Schedule)>| items.iter().map(|(item, _)| *item).collect();
+    (ids(learning), ids(reviews), ids(new))
+}
+
+pub fn study_queue(deck: &Deck, now: DateTime<Utc>) -> VecDeque<ReviewItem> {
+    study_queue_in(deck, now, &Local)
+}
+
+fn study_queue_in(
+    deck: &Deck,
+    now: DateTime<Utc>,
+    timezone: &impl TimeZone,
+) -> VecDeque<ReviewItem> {
+    let (learning, reviews, new) = split_queue(deck, now, timezone);
+    let mut queue: VecDeque<ReviewItem> = learning.into();
+    match deck.daily.new_card_order {
+        NewCardOrder::Before => {
+            queue.extend(new);
+            queue.extend(reviews);
+        }
+        NewCardOrder::After => {
+            queue.extend(reviews);
+            queue.extend(new);
+        }
+        NewCardOrder::Interleaved => queue.extend(interleave(reviews, new)),
+    }
+    queue
+}
+
+pub fn queue_counts(deck: &Deck, now: DateTime<Utc>) -> QueueCounts {
+    let (learning, reviews, new) = split_queue(deck, now, &Local);
+    QueueCounts {
+        new: new.len(),
+        learning: learning.len(),
+        due: reviews.len(),
+    }
+}
+
+//Spreads the new cards evenly between the reviews
+fn interleave<T>(reviews: Vec<T>, new: Vec<T>) -> Vec<T> {
+    let (review_count, new_count) = (reviews.len(), new.len());
+    let mut mixed = Vec::with_capacity(review_count + new_count);
+    let mut new = new.into_iter().enumerate().peekable();
+    for (position, review) in reviews.into_iter().enumerate() {
+        // The k-th new card goes after (k + 1) / (new_count + 1) of the reviews
+        while new
+            .next_if(|(k, _)| (k + 1) * review_count / (new_count + 1) <= position)
+            .map(|(_, id)| mixed.push(id))
+            .is_some()
+        {}
+        mixed.push(review);
+    }
+    mixed.extend(new.map(|(_, id)| id));
+    mixed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::scheduler::Grade;
+    use chrono::FixedOffset;
+    use std::path::Path;
+
+    #[test]
+    fn rollover_hour() {
+        let timezone = FixedOffset::east_opt(2 * 3600).unwrap();
+        let time = |text: &str| DateTime::parse_from_rfc3339(text).unwrap().to_utc();
+        assert_eq!(
+            day_start(time("2024-03-10T03:30:00+02:00"), 4, &timezone),
+            time("2024-03-09T04:00:00+02:00")
+        );
+        assert_eq!(
+            day_start(time("2024-03-10T04:00:00+02:00"), 4, &timezone),
+            time("2024-03-10T04:00:00+02:00")
+        );
+        let daily: DailyLimits = toml::from_str("rollover_hour = 23").unwrap();
+        assert_eq!(daily.rollover_hour, 23);
+        assert!(toml::from_str::<DailyLimits>("rollover_hour = 24").is_err());
+    }
+
+    #[test]
+    fn limits_and_mixing() {
+        let now = DateTime::<Utc>::from_timestamp(1_000 * 86400 + 12 * 3600, 0).unwrap();
+        let mut deck = Deck::new(String::from("test"), Path::new("/nonexistent"));
+        for id in 1..=10 {
+            let mut card = Card::default();
+            if id <= 6 {
+                let schedule = Schedule {
+                    state: CardState::Review,
+                    due: Some(now - Duration::days(id as i64)),
+                    ..Schedule::default()
+                };
+                card.set_schedule(0, schedule);
+            }
+            card.id = id;
+            deck.cards.push(card);
+        }
+        deck.daily.new_cards = 3;
+        deck.daily.reviews = 5;
+        deck.daily.rollover_hour = 0;
+        // One review and one new card were already studied today
+        for (card_id, days_ago) in [(1, 3), (1, 0), (42, 0)] {
+            deck.review_log.reviews.push(Review {
+                item: ReviewItem {
+                    card_id,
+                    ordinal: 0,
+                },
+                time: now - Duration::days(days_ago) - Duration::hours(1),
+                grade: Grade::Good,
+                time_taken: Duration::zero(),
+                change: None,
+            });
+        }
+        let ids = |items: VecDeque<ReviewItem>| -> Vec<u64> {
+            items.iter().map(|item| item.card_id).collect()
+        };
+        let (learning, reviews, new) = split_queue(&deck, now, &Utc);
+        assert!(learning.is_empty());
+        assert_eq!(ids(reviews.into()), vec![6, 5, 4, 3]);
+        assert_eq!(ids(new.into()), vec![7, 8]);
+        assert_eq!(
+            ids(study_queue_in(&deck, now, &Utc)),
+            vec![6, 7, 5, 8, 4, 3]
+        );
+        deck.daily.new_card_order = NewCardOrder::Before;
+        assert_eq!(
+            ids(study_queue_in(&deck, now, &Utc)),
+            vec![7, 8, 6, 5, 4, 3]
+        );
+        assert_eq!(interleave(vec![], vec![1, 2]), vec![1, 2]);
+    }
+}